@@ -0,0 +1,44 @@
+// Flags the points in a series that are unusually high compared to the
+// `window` points before them. The baseline is the median of the window and
+// the spread is the median absolute deviation, so earlier spikes don't hide
+// later ones. Returns (index, baseline) for each point flagged.
+pub fn find_spikes(
+    series: &Vec<usize>,
+    window: usize,
+    threshold: f64,
+    min_value: usize,
+) -> Vec<(usize, usize)> {
+    let mut spikes: Vec<(usize, usize)> = Vec::new();
+    // A few points are needed before the baseline means anything
+    let min_window = (window / 4).max(3);
+    for i in min_window..series.len() {
+        if series[i] < min_value {
+            continue;
+        }
+        let history: Vec<f64> = series[i.saturating_sub(window)..i]
+            .iter()
+            .map(|v| *v as f64)
+            .collect();
+        let median = get_median(&history);
+        let deviations: Vec<f64> = history.iter().map(|v| (v - median).abs()).collect();
+        // Scaled so that it estimates the standard deviation of normally
+        // distributed data, with a floor so flat series don't flag every change
+        let spread = (get_median(&deviations) * 1.4826).max(1.0);
+        if (series[i] as f64 - median) / spread >= threshold {
+            spikes.push((i, median.round() as usize));
+        }
+    }
+    return spikes;
+}
+
+fn get_median(values: &Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    match sorted.len() {
+        n if n % 2 == 0 => return (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+        n => return sorted[n / 2],
+    }
+}
@@ -0,0 +1,227 @@
+use crate::format_date_config;
+use crate::geoip::parse_network;
+use crate::privacy::anonymize_ip;
+use crate::security::SecurityReport;
+use crate::timezone::to_report_time;
+use crate::Entry;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::FixedOffset;
+use chrono::Local;
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::net::IpAddr;
+use yaml_rust::Yaml;
+
+// An address or network to block, with the reasons it was listed and the
+// last time it misbehaved.
+struct Block {
+    network: String,
+    reasons: BTreeSet<String>,
+    last_seen: DateTime<FixedOffset>,
+}
+
+// Writes the IP addresses found by the security rules, brute force detection
+// and request rate threshold to blocklist-file. Addresses are only exported
+// in the form they may appear in the report, so truncated addresses become
+// networks and nothing is exported when addresses are hashed.
+pub fn write_blocklist(
+    entries: &Vec<Entry>,
+    security: &SecurityReport,
+    timezone: Option<Tz>,
+    config: &Yaml,
+) {
+    let path = config["blocklist-file"].as_str().unwrap_or("");
+    if path.is_empty() || entries.is_empty() {
+        return;
+    }
+    let privacy = config["ip-privacy"]
+        .as_str()
+        .unwrap_or("none")
+        .to_lowercase();
+    if privacy.eq("hash") {
+        eprintln!("error: The blocklist can't be exported while ip-privacy is set to hash");
+        return;
+    }
+    let format = config["blocklist-format"].as_str().unwrap_or("plain");
+    if !["plain", "apache", "nginx", "ipset"].contains(&format) {
+        eprintln!("error: Invalid blocklist format: {}", format);
+        return;
+    }
+    let sources: Vec<&str> = match config["blocklist-sources"].as_vec() {
+        Some(list) => list.iter().filter_map(|s| s.as_str()).collect(),
+        None => vec!["security", "brute-force", "request-rate"],
+    };
+    let mut offenders: Vec<(String, String, DateTime<FixedOffset>)> = Vec::new();
+    if sources.contains(&"security") {
+        for finding in &security.findings {
+            offenders.push((
+                finding.ip.clone(),
+                finding.category.clone(),
+                finding.last_seen,
+            ));
+        }
+    }
+    if sources.contains(&"brute-force") {
+        for source in &security.brute_force {
+            offenders.push((
+                source.source.clone(),
+                String::from("Brute force"),
+                source.last_seen,
+            ));
+        }
+    }
+    let rate = config["blocklist-request-rate"].as_i64().unwrap_or(0) as usize;
+    if sources.contains(&"request-rate") && rate > 0 {
+        for (ip, rates) in &security.rates {
+            if rates.per_minute >= rate {
+                offenders.push((ip.clone(), String::from("Request rate"), rates.last_seen));
+            }
+        }
+    }
+
+    let mut whitelist: Vec<(u128, u128)> = Vec::new();
+    for network in config["blocklist-whitelist"]
+        .as_vec()
+        .unwrap_or(&Vec::new())
+    {
+        let network = network.as_str().unwrap_or("");
+        match get_network_range(network) {
+            Some(range) => whitelist.push(range),
+            None => eprintln!("error: Invalid blocklist whitelist entry: {}", network),
+        }
+    }
+    // Expiry is measured from the newest entry rather than the current time
+    // so that the output only depends on the logs
+    let latest = entries.iter().map(|e| e.time).max().unwrap();
+    let expiry = config["blocklist-expiry"].as_i64().unwrap_or(86400);
+    // Keyed by the start of the range so that the output is sorted by address
+    let mut blocks: BTreeMap<(u128, u128), Block> = BTreeMap::new();
+    for (ip, reason, last_seen) in offenders {
+        if expiry > 0 && (latest - last_seen).num_seconds() >= expiry {
+            continue;
+        }
+        let address = ip.parse::<IpAddr>();
+        if address.is_err() {
+            continue;
+        }
+        let network = if privacy.eq("truncate") {
+            let prefix = if address.unwrap().is_ipv4() { 24 } else { 48 };
+            format!("{}/{}", anonymize_ip(&ip, config), prefix)
+        } else {
+            ip.clone()
+        };
+        let range = get_network_range(&network).unwrap();
+        if whitelist.iter().any(|w| w.0 <= range.1 && range.0 <= w.1) {
+            continue;
+        }
+        let block = blocks.entry(range).or_insert(Block {
+            network,
+            reasons: BTreeSet::new(),
+            last_seen,
+        });
+        block.reasons.insert(reason);
+        block.last_seen = block.last_seen.max(last_seen);
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    let header = format!(
+        "# Generated by SimpleStats on {}",
+        format_date_config(
+            &to_report_time(&Local::now().fixed_offset(), timezone),
+            config
+        )
+    );
+    let ipset_name = config["blocklist-ipset-name"]
+        .as_str()
+        .unwrap_or("simplestats");
+    match format {
+        "apache" => {
+            lines.push(header);
+            lines.push(String::from("<RequireAll>"));
+            lines.push(String::from("    Require all granted"));
+        }
+        "nginx" => lines.push(header),
+        "ipset" => {
+            let timeout = if expiry > 0 {
+                format!(" timeout {}", expiry)
+            } else {
+                String::new()
+            };
+            lines.push(format!(
+                "create {} hash:net family inet{} -exist",
+                ipset_name, timeout
+            ));
+            lines.push(format!(
+                "create {}-v6 hash:net family inet6{} -exist",
+                ipset_name, timeout
+            ));
+        }
+        _ => {}
+    }
+    for block in blocks.values() {
+        let reasons: Vec<&str> = block.reasons.iter().map(|r| r.as_str()).collect();
+        let expires = if expiry > 0 {
+            format!(
+                ", expires {}",
+                format_date_config(&(block.last_seen + Duration::seconds(expiry)), config)
+            )
+        } else {
+            String::new()
+        };
+        match format {
+            "apache" => {
+                // Apache doesn't allow comments at the end of a directive
+                lines.push(format!("    # {}{}", reasons.join(", "), expires));
+                lines.push(format!("    Require not ip {}", block.network));
+            }
+            "nginx" => {
+                lines.push(format!(
+                    "deny {}; # {}{}",
+                    block.network,
+                    reasons.join(", "),
+                    expires
+                ));
+            }
+            "ipset" => {
+                let set = if block.network.contains(':') {
+                    format!("{}-v6", ipset_name)
+                } else {
+                    ipset_name.to_string()
+                };
+                // Entries expire the same amount of time after they were last seen
+                let timeout = if expiry > 0 {
+                    format!(
+                        " timeout {}",
+                        expiry - (latest - block.last_seen).num_seconds()
+                    )
+                } else {
+                    String::new()
+                };
+                lines.push(format!("add {} {}{} -exist", set, block.network, timeout));
+            }
+            _ => lines.push(block.network.clone()),
+        }
+    }
+    if format.eq("apache") {
+        lines.push(String::from("</RequireAll>"));
+    }
+
+    let path = shellexpand::tilde(path).to_string();
+    if let Err(e) = fs::write(&path, lines.join("\n") + "\n") {
+        eprintln!("error: Unable to write blocklist to {}: {}", path, e);
+    }
+}
+
+// Single addresses are treated as /32 or /128 networks.
+fn get_network_range(network: &str) -> Option<(u128, u128)> {
+    if network.contains('/') {
+        return parse_network(network);
+    }
+    match network.parse::<IpAddr>().ok()? {
+        IpAddr::V4(_) => return parse_network(&format!("{}/32", network)),
+        IpAddr::V6(_) => return parse_network(&format!("{}/128", network)),
+    }
+}
@@ -0,0 +1,166 @@
+use crate::Entry;
+use yaml_rust::Yaml;
+
+const ASSET_EXTENSIONS: [&str; 22] = [
+    "css", "js", "mjs", "map", "png", "jpg", "jpeg", "gif", "svg", "ico", "webp", "avif", "bmp",
+    "woff", "woff2", "ttf", "otf", "eot", "mp4", "webm", "mp3", "txt",
+];
+
+const PAGE_CONTENT_TYPES: [&str; 2] = ["text/html", "application/xhtml+xml"];
+
+const ASSET_TYPES: [(&str, &[&str]); 6] = [
+    (
+        "Images",
+        &[
+            "png", "jpg", "jpeg", "gif", "svg", "ico", "webp", "avif", "bmp",
+        ],
+    ),
+    ("Fonts", &["woff", "woff2", "ttf", "otf", "eot"]),
+    ("Stylesheets", &["css"]),
+    ("Scripts", &["js", "mjs", "map"]),
+    ("Media", &["mp4", "webm", "mp3", "ogg", "wav"]),
+    ("Data", &["json"]),
+];
+
+pub struct PageClassification {
+    asset_path_prefixes: Vec<String>,
+    page_content_types: Vec<String>,
+    asset_extensions: Vec<String>,
+    // (type, extensions) in the order they're configured
+    asset_types: Vec<(String, Vec<String>)>,
+}
+
+pub fn get_page_classification(config: &Yaml) -> PageClassification {
+    let classification = &config["page-classification"];
+    return PageClassification {
+        asset_path_prefixes: get_list(&classification["asset-path-prefixes"], &[]),
+        page_content_types: get_list(&classification["page-content-types"], &PAGE_CONTENT_TYPES),
+        asset_extensions: get_list(&classification["asset-extensions"], &ASSET_EXTENSIONS),
+        asset_types: match classification["asset-types"].as_hash() {
+            Some(types) => types
+                .iter()
+                .map(|(name, extensions)| {
+                    (
+                        name.as_str().unwrap_or("Other").to_string(),
+                        get_list(extensions, &[]),
+                    )
+                })
+                .collect(),
+            None => ASSET_TYPES
+                .iter()
+                .map(|(name, extensions)| {
+                    (
+                        name.to_string(),
+                        extensions.iter().map(|e| e.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        },
+    };
+}
+
+// Whether a request is for a page rather than a static asset such as a
+// stylesheet, script, image or font. Path prefixes are checked first, then
+// the response's Content-Type if it was logged, then the file extension.
+pub fn is_pageview(entry: &Entry, classification: &PageClassification) -> bool {
+    let url = get_request_path(entry);
+    for prefix in &classification.asset_path_prefixes {
+        if url.starts_with(prefix) {
+            return false;
+        }
+    }
+    let content_type = get_content_type(entry);
+    if !content_type.is_empty() {
+        return classification
+            .page_content_types
+            .iter()
+            .any(|t| content_type.eq_ignore_ascii_case(t));
+    }
+    let extension = get_extension(url);
+    if extension.is_empty() {
+        return true;
+    }
+    return !classification
+        .asset_extensions
+        .iter()
+        .any(|e| extension.eq_ignore_ascii_case(e));
+}
+
+// The kind of asset requested, used to group the assets table. The
+// response's Content-Type is used if it was logged and is a well-known type,
+// otherwise the file extension is looked up in asset-types.
+pub fn get_asset_type<'a>(entry: &Entry, classification: &'a PageClassification) -> &'a str {
+    let content_type = get_content_type(entry);
+    if content_type.starts_with("image/") {
+        return "Images";
+    }
+    if content_type.contains("font") {
+        return "Fonts";
+    }
+    if content_type.eq("text/css") {
+        return "Stylesheets";
+    }
+    if content_type.contains("javascript") {
+        return "Scripts";
+    }
+    if content_type.starts_with("video/") || content_type.starts_with("audio/") {
+        return "Media";
+    }
+    if content_type.eq("application/json") {
+        return "Data";
+    }
+    let extension = get_extension(get_request_path(entry));
+    for (name, extensions) in &classification.asset_types {
+        if extensions.iter().any(|e| extension.eq_ignore_ascii_case(e)) {
+            return name;
+        }
+    }
+    return "Other";
+}
+
+// The requested path without the query string, taken from %U if it was
+// logged and from the request line otherwise.
+pub fn get_request_path(entry: &Entry) -> &str {
+    if !entry.url_excl_query.is_empty() {
+        return &entry.url_excl_query;
+    }
+    let url = entry.request.split(' ').nth(1).unwrap_or("");
+    return url.split('?').next().unwrap_or("");
+}
+
+// The MIME type without parameters such as charset, or an empty string if
+// it wasn't logged.
+fn get_content_type(entry: &Entry) -> String {
+    let content_type = entry
+        .content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+    if content_type.eq("-") {
+        return String::new();
+    }
+    return content_type;
+}
+
+fn get_extension(url: &str) -> String {
+    let name = url.rsplit('/').next().unwrap_or("");
+    match name.rsplit_once('.') {
+        Some((_, extension)) => return extension.to_lowercase(),
+        None => return String::new(),
+    }
+}
+
+fn get_list(value: &Yaml, default: &[&str]) -> Vec<String> {
+    match value.as_vec() {
+        Some(list) => {
+            return list
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(|v| v.to_string())
+                .collect();
+        }
+        None => return default.iter().map(|v| v.to_string()).collect(),
+    }
+}
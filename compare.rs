@@ -0,0 +1,127 @@
+use crate::Entry;
+use chrono::Datelike;
+use chrono::Duration;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use yaml_rust::Yaml;
+
+// The latest day, week or month in the report and the one before it. Weeks
+// start on Monday. The latest period usually ends partway through, so only
+// the same number of days at the start of the previous period are compared.
+pub struct Comparison {
+    name: &'static str,
+    previous_start: NaiveDate,
+    previous_end: NaiveDate,
+    current_start: NaiveDate,
+    current_end: NaiveDate,
+}
+
+pub fn get_comparison(entries: &Vec<Entry>, config: &Yaml) -> Option<Comparison> {
+    let latest = entries.iter().map(|e| e.time.date_naive()).max()?;
+    let (name, previous_start, current_start) =
+        match config["compare-period"].as_str().unwrap_or("none") {
+            "day" => ("Day", latest - Duration::days(1), latest),
+            "week" => {
+                let start = latest - Duration::days(latest.weekday().num_days_from_monday() as i64);
+                ("Week", start - Duration::days(7), start)
+            }
+            "month" => {
+                let start = latest.with_day(1)?;
+                ("Month", (start - Duration::days(1)).with_day(1)?, start)
+            }
+            _ => return None,
+        };
+    // Capped for months that are shorter than the latest one so far
+    let previous_end =
+        (previous_start + (latest - current_start)).min(current_start - Duration::days(1));
+    return Some(Comparison {
+        name,
+        previous_start,
+        previous_end,
+        current_start,
+        current_end: latest,
+    });
+}
+
+fn format_range(start: NaiveDate, end: NaiveDate) -> String {
+    if start == end {
+        return start.format("%-d %b").to_string();
+    }
+    return format!("{} - {}", start.format("%-d %b"), end.format("%-d %b"));
+}
+
+impl Comparison {
+    pub fn get_name(&self) -> String {
+        return self.name.to_lowercase();
+    }
+
+    // e.g. "11 Mar - 16 Mar vs 4 Mar - 9 Mar"
+    pub fn get_ranges(&self) -> String {
+        return format!(
+            "{} vs {}",
+            format_range(self.current_start, self.current_end),
+            format_range(self.previous_start, self.previous_end)
+        );
+    }
+
+    pub fn get_header(&self) -> String {
+        return format!("<th>Change ({})</th>", self.get_ranges());
+    }
+
+    // Some(true) for entries in the latest period and Some(false) for entries
+    // in the one before it.
+    pub fn get_period(&self, entry: &Entry) -> Option<bool> {
+        let date = entry.time.date_naive();
+        if date >= self.current_start {
+            return Some(true);
+        }
+        if date >= self.previous_start && date <= self.previous_end {
+            return Some(false);
+        }
+        return None;
+    }
+
+    // The number of hits for each key in the latest and previous periods.
+    // Entries without a key aren't counted.
+    pub fn count_hits(
+        &self,
+        entries: &Vec<Entry>,
+        key: impl Fn(&Entry) -> Option<String>,
+    ) -> HashMap<String, (usize, usize)> {
+        let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+        for entry in entries {
+            let period = self.get_period(entry);
+            if period.is_none() {
+                continue;
+            }
+            if let Some(key) = key(entry) {
+                let count = counts.entry(key).or_insert((0, 0));
+                if period.unwrap() {
+                    count.0 += 1;
+                } else {
+                    count.1 += 1;
+                }
+            }
+        }
+        return counts;
+    }
+}
+
+// Formats the change from `previous` to `current` as a table cell. Values
+// that only appear in the latest period are marked as new.
+pub fn format_change(current: usize, previous: usize, format: &dyn Fn(usize) -> String) -> String {
+    if previous == 0 {
+        if current == 0 {
+            return String::from("<td>-</td>");
+        }
+        return String::from("<td><span class=\"ss-new\">New</span></td>");
+    }
+    let sign = if current >= previous { "+" } else { "-" };
+    return format!(
+        "<td>{}{} ({}{:.2}%)</td>",
+        sign,
+        format(current.abs_diff(previous)),
+        sign,
+        current.abs_diff(previous) as f64 / previous as f64 * 100.0
+    );
+}
@@ -0,0 +1,190 @@
+use maxminddb::geoip2;
+use maxminddb::Reader;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use yaml_rust::Yaml;
+
+pub struct GeoLocation {
+    pub country: String,
+    pub city: String,
+}
+
+pub struct Network {
+    pub asn: u32,
+    pub organization: String,
+}
+
+pub enum AsnDatabase {
+    Mmdb(Reader<Vec<u8>>),
+    // (first address, last address, ASN, organization), sorted by first address
+    Csv(Vec<(u128, u128, u32, String)>),
+}
+
+// The databases are read once per run and shared by every table that needs
+// them. Each may be missing if it isn't configured or couldn't be read.
+pub struct GeoIpDatabases {
+    pub city: Option<Reader<Vec<u8>>>,
+    pub asn: Option<AsnDatabase>,
+}
+
+pub fn get_geoip_databases(config: &Yaml) -> GeoIpDatabases {
+    return GeoIpDatabases {
+        city: get_geoip_reader(config),
+        asn: get_asn_database(config),
+    };
+}
+
+fn get_geoip_reader(config: &Yaml) -> Option<Reader<Vec<u8>>> {
+    let path = config["geoip-database"].as_str().unwrap_or("");
+    if path.is_empty() {
+        return None;
+    }
+    let path = shellexpand::tilde(path).to_string();
+    match Reader::open_readfile(&path) {
+        Ok(reader) => return Some(reader),
+        Err(e) => {
+            eprintln!("error: Unable to read GeoIP database at {}: {}", &path, e);
+            return None;
+        }
+    }
+}
+
+pub fn lookup_location(reader: &Reader<Vec<u8>>, ip: &str) -> GeoLocation {
+    let mut location = GeoLocation {
+        country: String::from("(unknown)"),
+        city: String::from("(unknown)"),
+    };
+    let address = ip.parse::<IpAddr>();
+    if address.is_err() {
+        return location;
+    }
+    let record = reader.lookup::<geoip2::City>(address.unwrap());
+    if record.is_err() {
+        return location;
+    }
+    let record = record.unwrap();
+    if let Some(country) = record.country {
+        location.country = get_name(&country.names)
+            .or(country.iso_code)
+            .unwrap_or("(unknown)")
+            .to_string();
+    }
+    if let Some(city) = record.city {
+        if let Some(name) = get_name(&city.names) {
+            location.city = format!("{}, {}", name, location.country);
+        }
+    }
+    return location;
+}
+
+fn get_name<'a>(names: &Option<BTreeMap<&'a str, &'a str>>) -> Option<&'a str> {
+    return names.as_ref().and_then(|n| n.get("en").copied());
+}
+
+// Accepts either a GeoLite2/DB-IP ASN database in .mmdb format or a
+// GeoLite2-ASN-Blocks CSV file (network,autonomous_system_number,autonomous_system_organization).
+fn get_asn_database(config: &Yaml) -> Option<AsnDatabase> {
+    let path = config["asn-database"].as_str().unwrap_or("");
+    if path.is_empty() {
+        return None;
+    }
+    let path = shellexpand::tilde(path).to_string();
+    if path.to_lowercase().ends_with(".csv") {
+        match fs::read_to_string(&path) {
+            Ok(contents) => return Some(AsnDatabase::Csv(parse_asn_csv(&contents))),
+            Err(e) => {
+                eprintln!("error: Unable to read ASN database at {}: {}", &path, e);
+                return None;
+            }
+        }
+    }
+    match Reader::open_readfile(&path) {
+        Ok(reader) => return Some(AsnDatabase::Mmdb(reader)),
+        Err(e) => {
+            eprintln!("error: Unable to read ASN database at {}: {}", &path, e);
+            return None;
+        }
+    }
+}
+
+pub fn lookup_network(database: &AsnDatabase, ip: &str) -> Option<Network> {
+    let address = ip.parse::<IpAddr>().ok()?;
+    match database {
+        AsnDatabase::Mmdb(reader) => {
+            let record = reader.lookup::<geoip2::Asn>(address).ok()?;
+            return Some(Network {
+                asn: record.autonomous_system_number?,
+                organization: record
+                    .autonomous_system_organization
+                    .unwrap_or("(unknown)")
+                    .to_string(),
+            });
+        }
+        AsnDatabase::Csv(ranges) => {
+            let address = address_to_u128(address);
+            let i = ranges.partition_point(|r| r.0 <= address);
+            if i == 0 || ranges[i - 1].1 < address {
+                return None;
+            }
+            let range = &ranges[i - 1];
+            return Some(Network {
+                asn: range.2,
+                organization: range.3.clone(),
+            });
+        }
+    }
+}
+
+fn parse_asn_csv(contents: &str) -> Vec<(u128, u128, u32, String)> {
+    let mut ranges: Vec<(u128, u128, u32, String)> = Vec::new();
+    for line in contents.lines() {
+        let parts: Vec<&str> = line.splitn(3, ',').collect();
+        if parts.len() != 3 {
+            continue;
+        }
+        let asn = parts[1].trim().parse::<u32>();
+        let network = parse_network(parts[0].trim());
+        // Skips the header row along with anything else that isn't a valid block
+        if asn.is_err() || network.is_none() {
+            continue;
+        }
+        let (first, last) = network.unwrap();
+        ranges.push((
+            first,
+            last,
+            asn.unwrap(),
+            parts[2].trim().trim_matches('"').replace("\"\"", "\""),
+        ));
+    }
+    ranges.sort_by_key(|r| r.0);
+    return ranges;
+}
+
+pub fn parse_network(network: &str) -> Option<(u128, u128)> {
+    let (address, prefix) = network.split_once('/')?;
+    let address = address.parse::<IpAddr>().ok()?;
+    let mut prefix = prefix.parse::<u32>().ok()?;
+    if address.is_ipv4() {
+        prefix += 96;
+    }
+    if prefix > 128 {
+        return None;
+    }
+    let host_mask = if prefix == 0 {
+        u128::MAX
+    } else {
+        (1u128 << (128 - prefix)) - 1
+    };
+    let first = address_to_u128(address) & !host_mask;
+    return Some((first, first | host_mask));
+}
+
+// IPv4 addresses are mapped into the IPv6 space (::ffff:0:0/96) so that both
+// families can share one sorted list.
+fn address_to_u128(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(v4) => return u128::from(v4.to_ipv6_mapped()),
+        IpAddr::V6(v6) => return u128::from(v6),
+    }
+}
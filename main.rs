@@ -1,10 +1,51 @@
+use anomaly::find_spikes;
+use blocklist::write_blocklist;
 use chrono::DateTime;
+use chrono::Duration;
 use chrono::FixedOffset;
 use chrono::Local;
-use chrono::TimeZone;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono::Timelike;
+use chrono_tz::Tz;
+use classify::get_asset_type;
+use classify::get_page_classification;
+use classify::get_request_path;
+use classify::is_pageview;
+use classify::PageClassification;
+use compare::format_change;
+use compare::get_comparison;
+use compare::Comparison;
 use flate2::read::GzDecoder;
+use geoip::get_geoip_databases;
+use geoip::lookup_location;
+use geoip::lookup_network;
+use geoip::GeoIpDatabases;
+use geoip::GeoLocation;
 use linked_hash_map::LinkedHashMap;
+use privacy::anonymize_ip;
+use privacy::check_ip_privacy;
+use privacy::is_ip_privacy_enabled;
+use rdns::resolve_hostnames;
 use regex::Regex;
+use routes::get_route_rules;
+use routes::normalize_route;
+use security::get_brute_force;
+use security::get_security_report;
+use security::BruteForce;
+use security::Finding;
+use security::SecurityReport;
+use sessions::get_session_starts;
+use sessions::get_sessions;
+use sessions::Session;
+use sketch::format_distinct;
+use sketch::get_hll_error;
+use sketch::is_approximate_distinct;
+use sketch::DistinctCounter;
+use sketch::HyperLogLog;
+use sketch::QuantileSketch;
+use sketch::SpaceSaving;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::env::args;
@@ -12,12 +53,32 @@ use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::net::IpAddr;
 use std::path::Path;
 use std::process;
 use substring::Substring;
+use timezone::get_report_timezone;
+use timezone::to_report_time;
+use useragent::parse_user_agent;
+use useragent::UserAgent;
+use woothee::parser::Parser;
 use yaml_rust::Yaml;
 use yaml_rust::YamlLoader;
 
+mod anomaly;
+mod blocklist;
+mod classify;
+mod compare;
+mod geoip;
+mod privacy;
+mod rdns;
+mod routes;
+mod security;
+mod sessions;
+mod sketch;
+mod timezone;
+mod useragent;
+
 struct Entry {
     ip: String,
     user: String,
@@ -47,6 +108,7 @@ struct Entry {
     connection_status: char,
     bytes_received: i32,
     bytes_transferred: i32,
+    content_type: String,
     remote_logname: String,
     error_log_id: i32,
 }
@@ -75,6 +137,10 @@ fn main() {
     }
 
     let config = &YamlLoader::load_from_str(&config_contents.unwrap()).unwrap()[0];
+    let timezone = get_report_timezone(config);
+    if !check_ip_privacy(config) {
+        process::exit(1);
+    }
 
     let access_log_dir_str =
         shellexpand::tilde(config["access-log-dir"].as_str().unwrap()).to_string();
@@ -117,8 +183,16 @@ fn main() {
             }
         }
     }
+    // Converted once so that every table buckets and shows times in the
+    // report's timezone
+    for entry in &mut entries {
+        entry.time = to_report_time(&entry.time, timezone);
+    }
     if !no_write {
-        write_output(&entries, &log_keys, config);
+        let security = get_security_report(&entries, config);
+        let databases = get_geoip_databases(config);
+        write_output(&entries, &log_keys, &security, &databases, timezone, config);
+        write_blocklist(&entries, &security, timezone, config);
     }
 }
 
@@ -290,6 +364,7 @@ fn parse_parts(parts: &Vec<&str>, keys: &Vec<&str>, config: &Yaml) -> Entry {
         connection_status: get_part("%X", parts, keys).parse::<char>().unwrap_or('?'),
         bytes_received: get_part("%I", parts, keys).parse::<i32>().unwrap_or(0),
         bytes_transferred: get_part("%S", parts, keys).parse::<i32>().unwrap_or(0),
+        content_type: get_part("%{Content-Type}o", parts, keys),
     };
     return entry;
 }
@@ -303,29 +378,65 @@ fn get_part(key: &str, parts: &Vec<&str>, keys: &Vec<&str>) -> String {
     return String::new();
 }
 
-fn write_output(entries: &Vec<Entry>, log_keys: &Vec<&str>, config: &Yaml) {
+fn write_output(
+    entries: &Vec<Entry>,
+    log_keys: &Vec<&str>,
+    security: &SecurityReport,
+    databases: &GeoIpDatabases,
+    timezone: Option<Tz>,
+    config: &Yaml,
+) {
     let sections: HashMap<&str, Vec<&str>> = HashMap::from([
         ("generated-date", vec![]),
         ("header", vec!["%t"]),
+        ("notable-events", vec!["%t"]),
         ("overall", vec!["%h", "%O"]),
         ("yearly-table", vec!["%t", "%O", "%h"]),
         ("monthly-table", vec!["%t", "%O", "%h"]),
+        ("month-by-year-table", vec!["%t", "%O", "%h", "%>s"]),
+        ("weekly-table", vec!["%t", "%O", "%h", "%>s"]),
+        ("daily-table", vec!["%t", "%O", "%h", "%>s"]),
         ("day-of-month-table", vec!["%t", "%O", "%h"]),
         ("day-of-week-table", vec!["%t", "%O", "%h"]),
         ("hourly-table", vec!["%t", "%O", "%h"]),
+        ("heatmap", vec!["%t", "%O", "%h"]),
         ("ip-table", vec!["%t", "%O", "%h"]),
+        ("country-table", vec!["%h", "%O"]),
+        ("city-table", vec!["%h", "%O"]),
+        ("network-table", vec!["%h", "%O", "%>s"]),
         ("users-table", vec!["%u", "%t", "%O"]),
+        ("browser-table", vec!["%{User-Agent}i", "%O", "%h"]),
+        ("os-table", vec!["%{User-Agent}i", "%O", "%h"]),
+        ("device-table", vec!["%{User-Agent}i", "%O", "%h"]),
         ("user-agent-table", vec!["%{User-Agent}i", "%t", "%O", "%h"]),
         ("pages-table", vec!["%r", "%O", "%v"]),
+        ("routes-table", vec!["%r", "%O"]),
+        ("assets-table", vec!["%r", "%O"]),
+        ("entry-pages-table", vec!["%r", "%t", "%h"]),
+        ("exit-pages-table", vec!["%r", "%t", "%h"]),
         ("files-table", vec!["%f", "%O", "%t"]),
         ("queries-table", vec!["%q"]),
+        ("top-paths-table", vec!["%r", "%t", "%h"]),
         ("referers-table", vec!["%{Referer}i", "%O"]),
         ("responses-table", vec!["%>s", "%O"]),
+        ("errors-by-day-table", vec!["%t", "%>s"]),
+        ("errors-by-hour-table", vec!["%t", "%>s"]),
+        ("error-urls-table", vec!["%r", "%>s"]),
+        ("broken-links-table", vec!["%r", "%>s", "%{Referer}i"]),
         ("time-taken-table", vec!["%D"]),
+        ("latency-tables", vec!["%t", "%r", "%>s"]),
+        ("slowest-endpoints-table", vec!["%r"]),
+        ("slo-tables", vec!["%t", "%r", "%>s", "%D"]),
+        ("security-findings-table", vec!["%h", "%t", "%r"]),
+        ("brute-force-tables", vec!["%h", "%t", "%r", "%>s"]),
+        ("top-talkers-table", vec!["%h", "%t", "%O"]),
+        ("rate-limits-table", vec!["%h", "%t", "%r", "%>s"]),
+        ("visitor-timelines", vec!["%h", "%t", "%r", "%>s"]),
         ("footer", vec![]),
     ]);
     let mut template =
         String::from(std::str::from_utf8(include_bytes!("templates/main.html")).unwrap());
+    let sessions = get_sessions(entries, config);
     'outer: for (key, value) in sections {
         for k in value {
             if !log_keys.contains(&k) {
@@ -335,7 +446,9 @@ fn write_output(entries: &Vec<Entry>, log_keys: &Vec<&str>, config: &Yaml) {
         }
         template = template.replace(
             &format!("{{{{{}}}}}", key),
-            &get_output(key, entries, &config),
+            &get_output(
+                key, entries, &sessions, security, databases, timezone, &config,
+            ),
         );
     }
 
@@ -358,63 +471,192 @@ fn write_output(entries: &Vec<Entry>, log_keys: &Vec<&str>, config: &Yaml) {
     }
 }
 
-fn get_output(key: &str, entries: &Vec<Entry>, config: &Yaml) -> String {
+fn get_output(
+    key: &str,
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    security: &SecurityReport,
+    databases: &GeoIpDatabases,
+    timezone: Option<Tz>,
+    config: &Yaml,
+) -> String {
     let mut total_size = 0usize;
     for entry in entries {
         total_size += entry.size as usize;
     }
     match key {
         "generated-date" => {
-            return get_generated_date(config);
+            return get_generated_date(timezone, config);
         }
         "header" => {
             return get_header(entries, config);
         }
+        "notable-events" => {
+            return get_notable_events(entries, config);
+        }
         "overall" => {
-            return get_overall(entries, total_size);
+            return get_overall(entries, sessions, total_size, config);
         }
         "yearly-table" => {
-            return get_yearly_table(entries, total_size);
+            return get_yearly_table(entries, sessions, total_size, config);
         }
         "monthly-table" => {
-            return get_monthly_table(entries, total_size);
+            return get_monthly_table(entries, sessions, total_size, config);
+        }
+        "month-by-year-table" => {
+            return get_calendar_table(entries, sessions, config, "Months", "Month", "%b %Y");
+        }
+        "weekly-table" => {
+            return get_calendar_table(entries, sessions, config, "Weeks", "Week", "%G-W%V");
+        }
+        "daily-table" => {
+            return get_calendar_table(entries, sessions, config, "Days", "Date", "%Y-%m-%d");
         }
         "day-of-month-table" => {
-            return get_day_of_month_table(entries, total_size);
+            return get_day_of_month_table(entries, sessions, total_size, config);
         }
         "day-of-week-table" => {
-            return get_day_of_week_table(entries, total_size);
+            return get_day_of_week_table(entries, sessions, total_size, config);
         }
         "hourly-table" => {
-            return get_hourly_table(entries, total_size);
+            return get_hourly_table(entries, sessions, total_size, config);
+        }
+        "heatmap" => {
+            return get_heatmap(entries, sessions, config);
         }
         "ip-table" => {
-            return get_ip_table(entries, total_size, config);
+            return get_ip_table(entries, sessions, total_size, databases, config);
+        }
+        "country-table" => {
+            return get_geoip_table(
+                entries,
+                total_size,
+                databases,
+                config,
+                "Countries",
+                "Country",
+                |l| l.country,
+            );
+        }
+        "city-table" => {
+            return get_geoip_table(
+                entries,
+                total_size,
+                databases,
+                config,
+                "Cities",
+                "City",
+                |l| l.city,
+            );
+        }
+        "network-table" => {
+            return get_network_table(entries, total_size, databases, config);
         }
         "users-table" => {
             return get_users_table(entries, total_size, config);
         }
+        "browser-table" => {
+            return get_user_agent_group_table(
+                entries,
+                total_size,
+                config,
+                "Browsers",
+                "Browser",
+                |ua| &ua.browser,
+            );
+        }
+        "os-table" => {
+            return get_user_agent_group_table(
+                entries,
+                total_size,
+                config,
+                "Operating Systems",
+                "Operating System",
+                |ua| &ua.os,
+            );
+        }
+        "device-table" => {
+            return get_user_agent_group_table(
+                entries,
+                total_size,
+                config,
+                "Devices",
+                "Device",
+                |ua| &ua.device,
+            );
+        }
         "user-agent-table" => {
             return get_user_agent_table(entries, total_size, config);
         }
         "pages-table" => {
             return get_pages_table(entries, total_size, config);
         }
+        "routes-table" => {
+            return get_routes_table(entries, total_size, config);
+        }
+        "assets-table" => {
+            return get_assets_table(entries, total_size, config);
+        }
+        "entry-pages-table" => {
+            return get_entry_pages_table(entries, sessions, config);
+        }
+        "exit-pages-table" => {
+            return get_exit_pages_table(entries, sessions, config);
+        }
         "files-table" => {
             return get_files_table(entries, total_size, config);
         }
         "queries-table" => {
             return get_queries_table(entries, config);
         }
+        "top-paths-table" => {
+            return get_top_paths_table(entries, sessions, config);
+        }
         "referers-table" => {
             return get_referers_table(entries, total_size, config);
         }
         "responses-table" => {
-            return get_responses_table(entries, total_size);
+            return get_responses_table(entries, total_size, config);
+        }
+        "errors-by-day-table" => {
+            return get_errors_table(entries, "Errors by Day", "Day", "%Y-%m-%d");
+        }
+        "errors-by-hour-table" => {
+            return get_errors_table(entries, "Errors by Hour", "Hour", "%H");
+        }
+        "error-urls-table" => {
+            return get_error_urls_table(entries, config);
+        }
+        "broken-links-table" => {
+            return get_broken_links_table(entries, config);
+        }
+        "security-findings-table" => {
+            return get_security_findings_table(security, config);
+        }
+        "brute-force-tables" => {
+            return get_brute_force_tables(entries, security, config);
+        }
+        "top-talkers-table" => {
+            return get_top_talkers_table(entries, total_size, security, config);
+        }
+        "rate-limits-table" => {
+            return get_rate_limits_table(security, config);
         }
         "time-taken-table" => {
             return get_time_taken_table(entries);
         }
+        "latency-tables" => {
+            return get_latency_tables(entries, config);
+        }
+        "slowest-endpoints-table" => {
+            return get_slowest_endpoints_table(entries, config);
+        }
+        "slo-tables" => {
+            return get_slo_tables(entries, config);
+        }
+        "visitor-timelines" => {
+            return get_visitor_timelines(entries, sessions, config);
+        }
         "footer" => {
             return get_footer();
         }
@@ -424,10 +666,11 @@ fn get_output(key: &str, entries: &Vec<Entry>, config: &Yaml) -> String {
     }
 }
 
-fn get_generated_date(config: &Yaml) -> String {
-    return Local::now()
-        .format(&config["output-date-format"].as_str().unwrap())
-        .to_string();
+fn get_generated_date(timezone: Option<Tz>, config: &Yaml) -> String {
+    return format_date_config(
+        &to_report_time(&Local::now().fixed_offset(), timezone),
+        config,
+    );
 }
 
 fn get_header(entries: &Vec<Entry>, config: &Yaml) -> String {
@@ -446,99 +689,404 @@ fn get_header(entries: &Vec<Entry>, config: &Yaml) -> String {
         );
 }
 
-fn get_overall(entries: &Vec<Entry>, total_size: usize) -> String {
-    let mut count = 0;
-    let mut added: Vec<String> = Vec::new();
+// Flags unusual hours and days. Requests and server errors per hour are
+// compared against a rolling baseline, and each day is checked for a single
+// visitor using most of the bandwidth and for new referers among the top ones.
+fn get_notable_events(entries: &Vec<Entry>, config: &Yaml) -> String {
+    let window = config["anomaly-window"].as_i64().unwrap_or(168) as usize;
+    let threshold = config["anomaly-threshold"]
+        .as_f64()
+        .or(config["anomaly-threshold"].as_i64().map(|t| t as f64))
+        .unwrap_or(5.0);
+    let ip_share = config["anomaly-ip-share"].as_i64().unwrap_or(50) as usize;
+    if window == 0 || entries.is_empty() {
+        return String::new();
+    }
+    let log_format = config["log-format"].as_str().unwrap_or("");
+    // (time, event, details)
+    let mut events: Vec<(NaiveDateTime, &str, String)> = Vec::new();
+
+    // (hits, server errors) for each hour
+    let mut hours: HashMap<NaiveDateTime, (usize, usize)> = HashMap::new();
+    for entry in entries {
+        let time = entry.time.naive_local();
+        let hour = time.date().and_hms_opt(time.hour(), 0, 0).unwrap();
+        let counts = hours.entry(hour).or_insert((0, 0));
+        counts.0 += 1;
+        counts.1 += entry.response.starts_with('5') as usize;
+    }
+    let first = *hours.keys().min().unwrap();
+    let last = *hours.keys().max().unwrap();
+    let mut times: Vec<NaiveDateTime> = Vec::new();
+    let mut time = first;
+    while time <= last {
+        times.push(time);
+        time += Duration::hours(1);
+    }
+    let hits: Vec<usize> = times
+        .iter()
+        .map(|t| hours.get(t).map(|c| c.0).unwrap_or(0))
+        .collect();
+    for (i, baseline) in find_spikes(&hits, window, threshold, 10) {
+        events.push((
+            times[i],
+            "Traffic spike",
+            format!("{} requests (usually about {})", hits[i], baseline),
+        ));
+    }
+    if log_format.contains("%>s") {
+        let errors: Vec<usize> = times
+            .iter()
+            .map(|t| hours.get(t).map(|c| c.1).unwrap_or(0))
+            .collect();
+        for (i, baseline) in find_spikes(&errors, window, threshold, 5) {
+            events.push((
+                times[i],
+                "Server errors",
+                format!(
+                    "{} responses with a 5xx code (usually about {})",
+                    errors[i], baseline
+                ),
+            ));
+        }
+    }
+
+    // (total bandwidth, bandwidth by IP) and hits by referer for each day
+    let mut days: BTreeMap<NaiveDate, (usize, HashMap<&str, usize>)> = BTreeMap::new();
+    let mut day_referers: BTreeMap<NaiveDate, HashMap<&str, usize>> = BTreeMap::new();
+    for entry in entries {
+        let date = entry.time.date_naive();
+        let day = days.entry(date).or_default();
+        day.0 += entry.size as usize;
+        *day.1.entry(&entry.ip).or_insert(0) += entry.size as usize;
+        let referers = day_referers.entry(date).or_default();
+        if !entry.referer.is_empty() && !entry.referer.eq("-") {
+            *referers.entry(&entry.referer).or_insert(0) += 1;
+        }
+    }
+    let window_days = (window / 24).max(1) as i64;
+    let mut last_seen: HashMap<&str, NaiveDate> = HashMap::new();
+    for (date, (size, ips)) in &days {
+        let referers = &day_referers[date];
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+        if log_format.contains("%O") && ips.len() > 1 {
+            let (ip, ip_size) = ips.iter().max_by_key(|i| i.1).unwrap();
+            if *size > 0 && ip_size * 100 >= size * ip_share {
+                events.push((
+                    midnight,
+                    "Heavy bandwidth use",
+                    format!(
+                        "{} used {} of {} ({})",
+                        anonymize_ip(ip, config),
+                        human_readable_bytes(*ip_size),
+                        human_readable_bytes(*size),
+                        format_percent(*ip_size, *size)
+                    ),
+                ));
+            }
+        }
+        // Referers can only be new once there is some history to compare against
+        if date > days.keys().next().unwrap() {
+            let mut top: Vec<(&&str, &usize)> = referers.iter().collect();
+            top.sort_by(|a, b| b.1.cmp(a.1));
+            for (referer, count) in top.iter().take(5) {
+                let seen = last_seen.get(**referer);
+                if seen.is_none() || (*date - *seen.unwrap()).num_days() > window_days {
+                    events.push((
+                        midnight,
+                        "New top referer",
+                        format!(
+                            "{} sent {} requests",
+                            truncate_string(&escape_html(referer), "referer", config, true),
+                            count
+                        ),
+                    ));
+                }
+            }
+        }
+        for referer in referers.keys() {
+            last_seen.insert(referer, *date);
+        }
+    }
+
+    events.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut lines: Vec<String> = Vec::new();
+    for (time, event, details) in events {
+        // Daily events are shown without a time
+        let format = if event.eq("Heavy bandwidth use") || event.eq("New top referer") {
+            "%Y-%m-%d"
+        } else {
+            "%Y-%m-%d %H:00"
+        };
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td class=\"ss-referer\">{}</td></tr>\n",
+            time.format(format),
+            event,
+            details
+        ));
+    }
+    if lines.is_empty() {
+        lines.push(String::from(
+            "<tr><td colspan=\"3\">Nothing unusual was found</td></tr>\n",
+        ));
+    }
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/notable-events.html")).unwrap());
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+fn get_overall(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
+    let mut visitors = DistinctCounter::new(config);
+    let mut pageviews = 0;
     for entry in entries {
-        if !added.contains(&entry.ip) {
-            count += 1;
-            added.push(entry.ip.clone());
+        visitors.insert(&entry.ip);
+        if is_pageview(entry, &classification) {
+            pageviews += 1;
+        }
+    }
+    let mut page_sessions = 0;
+    let mut bounces = 0;
+    for session in sessions {
+        let pages = get_session_pageviews(session, entries, &classification).len();
+        if pages > 0 {
+            page_sessions += 1;
+        }
+        if pages == 1 {
+            bounces += 1;
         }
     }
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/overall.html")).unwrap());
     return template
-        .replace("{{visitors}}", &count.to_string())
-        .replace("{{visits}}", &entries.len().to_string())
+        .replace("{{change-row}}", &get_overall_change_row(entries, sessions, config))
+        .replace(
+            "{{change-note}}",
+            &get_comparison(entries, config)
+                .map(|c| {
+                    format!(
+                        "<p>The second row shows the change from the previous {} to the latest one ({}).</p>",
+                        c.get_name(),
+                        c.get_ranges()
+                    )
+                })
+                .unwrap_or_default(),
+        )
+        .replace("{{visitors}}", &format_distinct(visitors.count(), config))
+        .replace(
+            "{{estimate-note}}",
+            is_approximate_distinct(config)
+                .then(|| "<p>Visitor counts marked with ~ are estimates.</p>")
+                .unwrap_or(""),
+        )
+        .replace("{{visits}}", &sessions.len().to_string())
+        .replace("{{hits}}", &entries.len().to_string())
+        .replace("{{pageviews}}", &pageviews.to_string())
+        .replace("{{bounce-rate}}", &format_percent(bounces, page_sessions))
         .replace("{{bandwidth}}", &human_readable_bytes(total_size));
 }
 
-fn get_yearly_table(entries: &Vec<Entry>, total_size: usize) -> String {
+// Compares the latest period to the previous one if compare-period is set.
+fn get_overall_change_row(entries: &Vec<Entry>, sessions: &Vec<Session>, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    let comparison = get_comparison(entries, config);
+    if comparison.is_none() {
+        return String::new();
+    }
+    let comparison = comparison.unwrap();
+    // Index 0 is the latest period and index 1 the previous one
+    let mut visitors: [HashSet<&str>; 2] = [HashSet::new(), HashSet::new()];
+    let mut visits = [0usize; 2];
+    let mut hits = [0usize; 2];
+    let mut pageviews = [0usize; 2];
+    let mut sizes = [0usize; 2];
+    for entry in entries {
+        if let Some(current) = comparison.get_period(entry) {
+            let i = (!current) as usize;
+            visitors[i].insert(&entry.ip);
+            hits[i] += 1;
+            pageviews[i] += is_pageview(entry, &classification) as usize;
+            sizes[i] += entry.size as usize;
+        }
+    }
+    for session in sessions {
+        if let Some(current) = comparison.get_period(&entries[session.entries[0]]) {
+            visits[(!current) as usize] += 1;
+        }
+    }
+    let count = |v: usize| v.to_string();
+    return format!(
+        "<tr>{}{}{}{}<td></td>{}</tr>\n",
+        format_change(visitors[0].len(), visitors[1].len(), &count),
+        format_change(visits[0], visits[1], &count),
+        format_change(hits[0], hits[1], &count),
+        format_change(pageviews[0], pageviews[1], &count),
+        format_change(sizes[0], sizes[1], &human_readable_bytes)
+    );
+}
+
+fn format_change_cell(
+    comparison: &Option<Comparison>,
+    changes: &HashMap<String, (usize, usize)>,
+    key: &str,
+) -> String {
+    if comparison.is_none() {
+        return String::new();
+    }
+    let (current, previous) = changes.get(key).copied().unwrap_or((0, 0));
+    return format_change(current, previous, &|v| v.to_string());
+}
+
+fn get_change_header(comparison: &Option<Comparison>) -> String {
+    return comparison
+        .as_ref()
+        .map(|c| c.get_header())
+        .unwrap_or_default();
+}
+
+fn get_yearly_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
+    let starts = get_session_starts(sessions, entries.len());
     let mut years: HashMap<i32, i32> = HashMap::new();
+    let mut visits: HashMap<i32, i32> = HashMap::new();
+    let mut pageviews: HashMap<i32, i32> = HashMap::new();
     let mut sizes: HashMap<i32, usize> = HashMap::new();
-    let mut unique: HashMap<i32, HashSet<&str>> = HashMap::new();
-    for entry in entries {
+    let unique = get_visitor_rollup(&get_daily_visitors(entries, config), "%Y", config);
+    for (i, entry) in entries.iter().enumerate() {
         let year = format_date(&entry.time, "%Y").parse::<i32>().unwrap();
         years.insert(year, *years.get(&year).unwrap_or(&0i32) + 1);
+        if starts[i] {
+            visits.insert(year, *visits.get(&year).unwrap_or(&0i32) + 1);
+        }
+        if is_pageview(entry, &classification) {
+            pageviews.insert(year, *pageviews.get(&year).unwrap_or(&0i32) + 1);
+        }
         sizes.insert(
             year,
             *sizes.get(&year).unwrap_or(&0usize) + entry.size as usize,
         );
-        if !unique.contains_key(&year) {
-            unique.insert(year, HashSet::new());
-        }
-        unique.get_mut(&year).unwrap().insert(&entry.ip);
     }
     let mut lines: Vec<String> = Vec::new();
     for (year, count) in years {
         lines.push(format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
             year,
-            unique[&year].len(),
+            format_distinct(unique[&year.to_string()].count(), config),
+            visits.get(&year).unwrap_or(&0i32),
             count,
             format_percent(count as usize, entries.len()),
+            pageviews.get(&year).unwrap_or(&0i32),
             human_readable_bytes(sizes[&year]),
             format_percent(sizes[&year], total_size)
         ));
     }
-    return format_table_vvb_avg("Yearly", "Year", &lines.join(""), "%Y", entries);
+    return format_table_vvb_avg(
+        "Yearly",
+        "Year",
+        &lines.join(""),
+        "%Y",
+        entries,
+        &starts,
+        config,
+    );
 }
 
-fn get_monthly_table(entries: &Vec<Entry>, total_size: usize) -> String {
+fn get_monthly_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
     let month_names = vec![
         "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
     ];
+    let starts = get_session_starts(sessions, entries.len());
     let mut months: HashMap<i32, i32> = HashMap::new();
+    let mut visits: HashMap<i32, i32> = HashMap::new();
+    let mut pageviews: HashMap<i32, i32> = HashMap::new();
     let mut sizes: HashMap<i32, usize> = HashMap::new();
-    let mut unique: HashMap<i32, HashSet<&str>> = HashMap::new();
-    for entry in entries {
+    let unique = get_visitor_rollup(&get_daily_visitors(entries, config), "%m", config);
+    for (i, entry) in entries.iter().enumerate() {
         let month = format_date(&entry.time, "%m").parse::<i32>().unwrap();
         months.insert(month, *months.get(&month).unwrap_or(&0i32) + 1);
+        if starts[i] {
+            visits.insert(month, *visits.get(&month).unwrap_or(&0i32) + 1);
+        }
+        if is_pageview(entry, &classification) {
+            pageviews.insert(month, *pageviews.get(&month).unwrap_or(&0i32) + 1);
+        }
         sizes.insert(
             month,
             *sizes.get(&month).unwrap_or(&0usize) + entry.size as usize,
         );
-        if !unique.contains_key(&month) {
-            unique.insert(month, HashSet::new());
-        }
-        unique.get_mut(&month).unwrap().insert(&entry.ip);
     }
     let mut lines: Vec<String> = Vec::new();
     for month in 1..13 {
         if !months.contains_key(&month) {
             months.insert(month, 0);
             sizes.insert(month, 0);
-            unique.insert(month, HashSet::new());
         }
         lines.push(format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
             month_names[(month - 1) as usize],
-            unique[&month].len(),
+            format_distinct(
+                unique
+                    .get(&format!("{:02}", month))
+                    .map(|u| u.count())
+                    .unwrap_or(0),
+                config
+            ),
+            visits.get(&month).unwrap_or(&0i32),
             months[&month],
             format_percent(months[&month] as usize, entries.len()),
+            pageviews.get(&month).unwrap_or(&0i32),
             human_readable_bytes(sizes[&month]),
             format_percent(sizes[&month], total_size)
         ));
     }
-    return format_table_vvb_avg("Monthly", "Month", &lines.join(""), "%m", entries);
+    return format_table_vvb_avg(
+        "Monthly",
+        "Month",
+        &lines.join(""),
+        "%Y-%m",
+        entries,
+        &starts,
+        config,
+    );
 }
 
-fn get_day_of_month_table(entries: &Vec<Entry>, total_size: usize) -> String {
+fn get_day_of_month_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
+    let starts = get_session_starts(sessions, entries.len());
     let mut days: HashMap<i32, i32> = HashMap::new();
+    let mut visits: HashMap<i32, i32> = HashMap::new();
+    let mut pageviews: HashMap<i32, i32> = HashMap::new();
     let mut sizes: HashMap<i32, usize> = HashMap::new();
-    for entry in entries {
+    for (i, entry) in entries.iter().enumerate() {
         let day = format_date(&entry.time, "%d").parse::<i32>().unwrap();
         days.insert(day, *days.get(&day).unwrap_or(&0i32) + 1);
+        if starts[i] {
+            visits.insert(day, *visits.get(&day).unwrap_or(&0i32) + 1);
+        }
+        if is_pageview(entry, &classification) {
+            pageviews.insert(day, *pageviews.get(&day).unwrap_or(&0i32) + 1);
+        }
         sizes.insert(
             day,
             *sizes.get(&day).unwrap_or(&0usize) + entry.size as usize,
@@ -551,24 +1099,143 @@ fn get_day_of_month_table(entries: &Vec<Entry>, total_size: usize) -> String {
             sizes.insert(day, 0);
         }
         lines.push(format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
             day,
+            visits.get(&day).unwrap_or(&0i32),
             days[&day],
             format_percent(days[&day] as usize, entries.len()),
+            pageviews.get(&day).unwrap_or(&0i32),
             human_readable_bytes(sizes[&day]),
             format_percent(sizes[&day], total_size)
         ));
     }
-    return format_table_vb_avg("Days of month", "Day", &lines.join(""), "%Y", entries);
+    return format_table_vb_avg(
+        "Days of month",
+        "Day",
+        &lines.join(""),
+        "%Y-%m-%d",
+        entries,
+        &starts,
+        config,
+    );
+}
+
+// One row for every period in the report's range, including periods without
+// any requests.
+fn get_calendar_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    config: &Yaml,
+    title: &str,
+    header: &str,
+    date_format: &str,
+) -> String {
+    let classification = get_page_classification(config);
+    let starts = get_session_starts(sessions, entries.len());
+    let mut hits: HashMap<String, usize> = HashMap::new();
+    let mut visits: HashMap<String, usize> = HashMap::new();
+    let mut pageviews: HashMap<String, usize> = HashMap::new();
+    let mut sizes: HashMap<String, usize> = HashMap::new();
+    let mut errors: HashMap<String, usize> = HashMap::new();
+    let unique = get_visitor_rollup(&get_daily_visitors(entries, config), date_format, config);
+    for (i, entry) in entries.iter().enumerate() {
+        let period = format_date(&entry.time, date_format);
+        hits.insert(period.clone(), *hits.get(&period).unwrap_or(&0usize) + 1);
+        if starts[i] {
+            visits.insert(period.clone(), *visits.get(&period).unwrap_or(&0usize) + 1);
+        }
+        if is_pageview(entry, &classification) {
+            pageviews.insert(
+                period.clone(),
+                *pageviews.get(&period).unwrap_or(&0usize) + 1,
+            );
+        }
+        if entry.response.starts_with('4') || entry.response.starts_with('5') {
+            errors.insert(period.clone(), *errors.get(&period).unwrap_or(&0usize) + 1);
+        }
+        sizes.insert(
+            period.clone(),
+            *sizes.get(&period).unwrap_or(&0usize) + entry.size as usize,
+        );
+    }
+    let periods = get_periods(entries, date_format);
+    let mut values: Vec<[usize; 6]> = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+    for period in &periods {
+        let v = [
+            unique.get(period).map(|u| u.count()).unwrap_or(0),
+            *visits.get(period).unwrap_or(&0usize),
+            *hits.get(period).unwrap_or(&0usize),
+            *pageviews.get(period).unwrap_or(&0usize),
+            *sizes.get(period).unwrap_or(&0usize),
+            *errors.get(period).unwrap_or(&0usize),
+        ];
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            period,
+            format_distinct(v[0], config),
+            v[1],
+            v[2],
+            v[3],
+            human_readable_bytes(v[4]),
+            v[5]
+        ));
+        values.push(v);
+    }
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/table-calendar.html")).unwrap());
+    let mut template = template
+        .replace("{{title}}", title)
+        .replace("{{key}}", header)
+        .replace("{{rows}}", &lines.join(""));
+    for (i, name) in [
+        "visitors",
+        "visits",
+        "hits",
+        "pageviews",
+        "bandwidth",
+        "errors",
+    ]
+    .iter()
+    .enumerate()
+    {
+        let totals: Vec<(String, usize)> = periods
+            .iter()
+            .zip(&values)
+            .map(|(period, v)| (period.clone(), v[i]))
+            .collect();
+        let format: &dyn Fn(usize) -> String = match i {
+            0 => &|v| format_distinct(v, config),
+            4 => &human_readable_bytes,
+            _ => &|v| v.to_string(),
+        };
+        template = replace_period_stats(template, name, &totals, format);
+    }
+    return template;
 }
 
-fn get_day_of_week_table(entries: &Vec<Entry>, total_size: usize) -> String {
-    let day_names = vec!["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+fn get_day_of_week_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
+    let day_names = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let starts = get_session_starts(sessions, entries.len());
     let mut days: HashMap<i32, i32> = HashMap::new();
+    let mut visits: HashMap<i32, i32> = HashMap::new();
+    let mut pageviews: HashMap<i32, i32> = HashMap::new();
     let mut sizes: HashMap<i32, usize> = HashMap::new();
-    for entry in entries {
+    for (i, entry) in entries.iter().enumerate() {
         let day = format_date(&entry.time, "%w").parse::<i32>().unwrap();
         days.insert(day, *days.get(&day).unwrap_or(&0i32) + 1);
+        if starts[i] {
+            visits.insert(day, *visits.get(&day).unwrap_or(&0i32) + 1);
+        }
+        if is_pageview(entry, &classification) {
+            pageviews.insert(day, *pageviews.get(&day).unwrap_or(&0i32) + 1);
+        }
         sizes.insert(
             day,
             *sizes.get(&day).unwrap_or(&0usize) + entry.size as usize,
@@ -581,10 +1248,12 @@ fn get_day_of_week_table(entries: &Vec<Entry>, total_size: usize) -> String {
             sizes.insert(day, 0);
         }
         lines.push(format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
             day_names[day as usize],
+            visits.get(&day).unwrap_or(&0i32),
             days[&day],
             format_percent(days[&day] as usize, entries.len()),
+            pageviews.get(&day).unwrap_or(&0i32),
             human_readable_bytes(sizes[&day]),
             format_percent(sizes[&day], total_size)
         ));
@@ -592,12 +1261,27 @@ fn get_day_of_week_table(entries: &Vec<Entry>, total_size: usize) -> String {
     return format_table_vb("Days of week", "Day", &lines.join(""));
 }
 
-fn get_hourly_table(entries: &Vec<Entry>, total_size: usize) -> String {
+fn get_hourly_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
+    let starts = get_session_starts(sessions, entries.len());
     let mut hours: HashMap<i32, i32> = HashMap::new();
+    let mut visits: HashMap<i32, i32> = HashMap::new();
+    let mut pageviews: HashMap<i32, i32> = HashMap::new();
     let mut sizes: HashMap<i32, usize> = HashMap::new();
-    for entry in entries {
+    for (i, entry) in entries.iter().enumerate() {
         let hour = format_date(&entry.time, "%H").parse::<i32>().unwrap();
         hours.insert(hour, *hours.get(&hour).unwrap_or(&0i32) + 1);
+        if starts[i] {
+            visits.insert(hour, *visits.get(&hour).unwrap_or(&0i32) + 1);
+        }
+        if is_pageview(entry, &classification) {
+            pageviews.insert(hour, *pageviews.get(&hour).unwrap_or(&0i32) + 1);
+        }
         sizes.insert(
             hour,
             *sizes.get(&hour).unwrap_or(&0usize) + entry.size as usize,
@@ -618,11 +1302,13 @@ fn get_hourly_table(entries: &Vec<Entry>, total_size: usize) -> String {
             h = (hour - 12).to_string();
         }
         lines.push(format!(
-            "<tr><td>{} {}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            "<tr><td>{} {}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
             format!("{:\u{00A0}>2}", h),
             (hour < 12).then(|| "AM").unwrap_or("PM"),
+            visits.get(&hour).unwrap_or(&0i32),
             hours[&hour],
             format_percent(hours[&hour] as usize, entries.len()),
+            pageviews.get(&hour).unwrap_or(&0i32),
             human_readable_bytes(sizes[&hour]),
             format_percent(sizes[&hour], total_size)
         ));
@@ -630,48 +1316,336 @@ fn get_hourly_table(entries: &Vec<Entry>, total_size: usize) -> String {
     return format_table_vb("Hourly", "Hour", &lines.join(""));
 }
 
-fn get_ip_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
+fn get_heatmap(entries: &Vec<Entry>, sessions: &Vec<Session>, config: &Yaml) -> String {
+    let day_names = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let metric = config["heatmap"].as_str().unwrap_or("visits");
+    if !metric.eq("visits") && !metric.eq("bandwidth") {
+        return String::new();
+    }
+    let starts = get_session_starts(sessions, entries.len());
+    let mut cells = [[0usize; 24]; 7];
+    for (i, entry) in entries.iter().enumerate() {
+        let day = format_date(&entry.time, "%w").parse::<usize>().unwrap();
+        let hour = format_date(&entry.time, "%H").parse::<usize>().unwrap();
+        if metric.eq("visits") {
+            cells[day][hour] += starts[i] as usize;
+        } else {
+            cells[day][hour] += entry.size as usize;
+        }
+    }
+    let max = cells.iter().flatten().max().copied().unwrap_or(0).max(1);
+    // Darker shades of the visits and bandwidth header colors
+    let color = if metric.eq("visits") {
+        "67, 160, 71"
+    } else {
+        "30, 136, 229"
+    };
+    let mut lines: Vec<String> = Vec::new();
+    for (day, hours) in cells.iter().enumerate() {
+        let mut line = format!("<tr><th>{}</th>", day_names[day]);
+        for value in hours {
+            line.push_str(&format!(
+                "<td style=\"background-color: rgba({}, {:.2})\" title=\"{}\"></td>",
+                color,
+                *value as f64 / max as f64,
+                if metric.eq("visits") {
+                    value.to_string()
+                } else {
+                    human_readable_bytes(*value)
+                }
+            ));
+        }
+        line.push_str("</tr>\n");
+        lines.push(line);
+    }
+    let header: Vec<String> = (0..24).map(|h| format!("<th>{:02}</th>", h)).collect();
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/heatmap.html")).unwrap());
+    return template
+        .replace(
+            "{{title}}",
+            if metric.eq("visits") {
+                "Visits"
+            } else {
+                "Bandwidth"
+            },
+        )
+        .replace("{{metric}}", metric)
+        .replace("{{header}}", &header.join(""))
+        .replace("{{rows}}", &lines.join(""));
+}
+
+fn get_ip_table(
+    entries: &Vec<Entry>,
+    sessions: &Vec<Session>,
+    total_size: usize,
+    databases: &GeoIpDatabases,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
     let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut visits: HashMap<String, i32> = HashMap::new();
+    let mut pageviews: HashMap<String, i32> = HashMap::new();
     let mut bw: HashMap<String, usize> = HashMap::new();
     let mut dates: HashMap<String, Vec<DateTime<FixedOffset>>> = HashMap::new();
+    // With privacy enabled several addresses can share a row, so one of them
+    // is kept for lookups that need the full address
+    let mut addresses: HashMap<String, &str> = HashMap::new();
+    let top = get_top_keys(entries, "ip-table", config, |e| anonymize_ip(&e.ip, config));
+    // (visits, hits, pageviews, bandwidth, addresses) of the rows left out
+    let mut other = (0usize, 0usize, 0usize, 0usize, new_other_counter(config));
     for entry in entries {
-        unique.insert(
-            entry.ip.clone(),
-            *unique.get(&entry.ip).unwrap_or(&0i32) + 1,
-        );
+        let ip = anonymize_ip(&entry.ip, config);
+        if top.as_ref().is_some_and(|t| !t.contains(&ip)) {
+            other.1 += 1;
+            other.2 += is_pageview(entry, &classification) as usize;
+            other.3 += entry.size as usize;
+            other.4.insert(&ip);
+            continue;
+        }
+        unique.insert(ip.clone(), *unique.get(&ip).unwrap_or(&0i32) + 1);
         bw.insert(
-            entry.ip.clone(),
-            *bw.get(&entry.ip).unwrap_or(&0usize) + entry.size as usize,
+            ip.clone(),
+            *bw.get(&ip).unwrap_or(&0usize) + entry.size as usize,
         );
-        if !dates.contains_key(&entry.ip) {
+        if !dates.contains_key(&ip) {
             let vec: Vec<DateTime<FixedOffset>> = Vec::new();
-            dates.insert(entry.ip.clone(), vec);
+            dates.insert(ip.clone(), vec);
+            addresses.insert(ip.clone(), &entry.ip);
+        }
+        dates.get_mut(&ip).unwrap().push(entry.time);
+        if is_pageview(entry, &classification) {
+            pageviews.insert(ip.clone(), *pageviews.get(&ip).unwrap_or(&0i32) + 1);
         }
-        dates.get_mut(&entry.ip).unwrap().push(entry.time);
+    }
+    for session in sessions {
+        let ip = anonymize_ip(&session.ip, config);
+        if top.as_ref().is_some_and(|t| !t.contains(&ip)) {
+            other.0 += 1;
+            continue;
+        }
+        visits.insert(ip.clone(), *visits.get(&ip).unwrap_or(&0i32) + 1);
     }
     unique = sort_map(unique);
+    let comparison = get_comparison(entries, config);
+    let changes = match &comparison {
+        Some(c) => c.count_hits(entries, |e| Some(anonymize_ip(&e.ip, config))),
+        None => HashMap::new(),
+    };
+    let reader = databases.city.as_ref();
+    let privacy = is_ip_privacy_enabled(config);
+    // Hostnames would identify the address, so they are never shown with privacy enabled
+    let lookups = if privacy {
+        0
+    } else {
+        config["reverse-dns-lookups"].as_i64().unwrap_or(0).max(0) as usize
+    };
+    let hostnames = resolve_hostnames(
+        &unique.keys().take(lookups).map(|ip| ip.as_str()).collect(),
+        config,
+    );
+    let timelines = get_timeline_ips(entries, config);
     let mut lines: Vec<String> = Vec::new();
     for (ip, count) in unique {
         dates
             .get_mut(&ip)
             .unwrap()
             .sort_by_key(|k| k.timestamp_millis());
+        let mut extra_columns = match reader {
+            Some(r) => format!("<td>{}</td>", lookup_location(r, addresses[&ip]).country),
+            None => String::new(),
+        };
+        if lookups > 0 {
+            extra_columns.push_str(&format!(
+                "<td>{}</td>",
+                get_or_none(hostnames.get(&ip).map(|h| h.as_str()).unwrap_or(""))
+            ));
+        }
+        if !timelines.is_empty() {
+            extra_columns.push_str(&if timelines.contains(&ip) {
+                format!("<td><a href=\"#timeline-{}\">View</a></td>", ip)
+            } else {
+                String::from("<td></td>")
+            });
+        }
+        // A hashed address can't be looked up
+        let whois = if privacy && ip.parse::<IpAddr>().is_err() {
+            String::from("-")
+        } else {
+            format!(
+                "<a href=\"{}\">View</a>",
+                config["whois-tool"]
+                    .as_str()
+                    .unwrap()
+                    .replace("<address>", &ip)
+            )
+        };
         lines.push(format!(
-					"<tr><td>{}</td><td><a href=\"{}\">View</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+					"<tr id=\"ip-{}\"><td>{}</td>{}<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>\n",
 					ip,
-					config["whois-tool"]
-					.as_str()
-					.unwrap()
-					.replace("<address>", &ip),
+					ip,
+					extra_columns,
+					whois,
+					visits.get(&ip).unwrap_or(&0i32),
 					count,
 					format_percent(count as usize, entries.len()),
+					pageviews.get(&ip).unwrap_or(&0i32),
 					human_readable_bytes(bw[&ip]),
 					format_percent(bw[&ip], total_size),
-					format_date_config(&dates[&ip][dates[&ip].len() - 1], &config)
+					format_date_config(&dates[&ip][dates[&ip].len() - 1], &config),
+					format_change_cell(&comparison, &changes, &ip)
 				));
     }
+    if other.1 > 0 {
+        let extra_count =
+            reader.is_some() as usize + (lookups > 0) as usize + !timelines.is_empty() as usize;
+        lines.push(format!(
+            "<tr><th>{}</th>{}<th></th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th></th>{}</tr>\n",
+            format_other_label(&other.4),
+            "<th></th>".repeat(extra_count),
+            other.0,
+            other.1,
+            format_percent(other.1, entries.len()),
+            other.2,
+            human_readable_bytes(other.3),
+            format_percent(other.3, total_size),
+            comparison.is_some().then(|| "<th></th>").unwrap_or("")
+        ));
+    }
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/ip-table.html")).unwrap());
+    return template
+        .replace(
+            "{{country-header}}",
+            reader.is_some().then(|| "<th>Country</th>").unwrap_or(""),
+        )
+        .replace(
+            "{{hostname-header}}",
+            (lookups > 0).then(|| "<th>Hostname</th>").unwrap_or(""),
+        )
+        .replace(
+            "{{timeline-header}}",
+            (!timelines.is_empty())
+                .then(|| "<th>Timeline</th>")
+                .unwrap_or(""),
+        )
+        .replace("{{change-header}}", &get_change_header(&comparison))
+        .replace("{{rows}}", &lines.join(""));
+}
+
+// The keys of the rows to show in a table limited by max-rows, found without
+// keeping a count for every distinct key. Returns None if the table isn't
+// limited.
+fn get_top_keys(
+    entries: &Vec<Entry>,
+    table: &str,
+    config: &Yaml,
+    key: impl Fn(&Entry) -> String,
+) -> Option<HashSet<String>> {
+    let max_rows = config["max-rows"][table].as_i64().unwrap_or(0).max(0) as usize;
+    if max_rows == 0 {
+        return None;
+    }
+    // Tracking more candidates than there are rows makes it likelier that the
+    // most frequent keys are all found
+    let mut top = SpaceSaving::new(max_rows * 10);
+    for entry in entries {
+        top.insert(&key(entry));
+    }
+    return Some(top.top(max_rows).into_iter().collect());
+}
+
+// The rows left out of a limited table are only counted with a sketch, even
+// if approximate-distinct isn't set, so that memory use stays bounded.
+fn new_other_counter(config: &Yaml) -> HyperLogLog {
+    return HyperLogLog::new(get_hll_error(config));
+}
+
+fn format_other_label(distinct: &HyperLogLog) -> String {
+    return format!("Other (~{} distinct)", distinct.estimate());
+}
+
+fn get_network_table(
+    entries: &Vec<Entry>,
+    total_size: usize,
+    databases: &GeoIpDatabases,
+    config: &Yaml,
+) -> String {
+    let database = databases.asn.as_ref();
+    if database.is_none() {
+        return String::new();
+    }
+    let database = database.unwrap();
+    let mut networks: HashMap<&str, String> = HashMap::new();
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut organizations: HashMap<String, String> = HashMap::new();
+    let mut bw: HashMap<String, usize> = HashMap::new();
+    let mut errors: HashMap<String, i32> = HashMap::new();
+    let mut visitors: HashMap<String, HashSet<&str>> = HashMap::new();
+    let mut ips: HashMap<String, LinkedHashMap<String, i32>> = HashMap::new();
+    // Addresses combined into the IP address table's Other row have no row to link to
+    let top = get_top_keys(entries, "ip-table", config, |e| anonymize_ip(&e.ip, config));
+    for entry in entries {
+        if !networks.contains_key(entry.ip.as_str()) {
+            let network = lookup_network(database, &entry.ip);
+            let asn = match network {
+                Some(n) => {
+                    let asn = format!("AS{}", n.asn);
+                    organizations.insert(asn.clone(), n.organization);
+                    asn
+                }
+                None => String::from("(unknown)"),
+            };
+            networks.insert(&entry.ip, asn);
+        }
+        let asn = &networks[entry.ip.as_str()];
+        unique.insert(asn.clone(), *unique.get(asn).unwrap_or(&0i32) + 1);
+        bw.insert(
+            asn.clone(),
+            *bw.get(asn).unwrap_or(&0usize) + entry.size as usize,
+        );
+        if entry.response.starts_with('4') || entry.response.starts_with('5') {
+            errors.insert(asn.clone(), *errors.get(asn).unwrap_or(&0i32) + 1);
+        }
+        if !ips.contains_key(asn) {
+            ips.insert(asn.clone(), LinkedHashMap::new());
+            visitors.insert(asn.clone(), HashSet::new());
+        }
+        visitors.get_mut(asn).unwrap().insert(&entry.ip);
+        let network_ips = ips.get_mut(asn).unwrap();
+        let ip = anonymize_ip(&entry.ip, config);
+        network_ips.insert(ip.clone(), *network_ips.get(&ip).unwrap_or(&0i32) + 1);
+    }
+    unique = sort_map(unique);
+    let mut lines: Vec<String> = Vec::new();
+    for (asn, count) in unique {
+        let mut network_ips: Vec<(&String, &i32)> = ips[&asn].iter().collect();
+        network_ips.sort_by(|a, b| b.1.cmp(a.1));
+        let links: Vec<String> = network_ips
+            .iter()
+            .map(|(ip, _)| {
+                if top.as_ref().is_some_and(|t| !t.contains(*ip)) {
+                    return ip.to_string();
+                }
+                return format!("<a href=\"#ip-{}\">{}</a>", ip, ip);
+            })
+            .collect();
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"ss-ip-list\"><details><summary>{}</summary>{}</details></td></tr>\n",
+            asn,
+            organizations.get(&asn).map(|o| o.as_str()).unwrap_or("(unknown)"),
+            visitors[&asn].len(),
+            count,
+            format_percent(count as usize, entries.len()),
+            human_readable_bytes(bw[&asn]),
+            format_percent(bw[&asn], total_size),
+            format_percent(*errors.get(&asn).unwrap_or(&0i32) as usize, count as usize),
+            network_ips.len(),
+            links.join("<br>")
+        ));
+    }
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/network-table.html")).unwrap());
     return template.replace("{{rows}}", &lines.join(""));
 }
 
@@ -718,7 +1692,7 @@ fn get_users_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> St
 
 fn get_user_agent_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
     let mut unique_agents: LinkedHashMap<String, i32> = LinkedHashMap::new();
-    let mut unique_visitors: HashMap<String, HashSet<&str>> = HashMap::new();
+    let mut unique_visitors: HashMap<String, DistinctCounter> = HashMap::new();
     let mut bw: HashMap<String, usize> = HashMap::new();
     let mut dates: HashMap<String, Vec<DateTime<FixedOffset>>> = HashMap::new();
     for entry in entries {
@@ -727,7 +1701,7 @@ fn get_user_agent_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml)
             *unique_agents.get(&entry.agent).unwrap_or(&0i32) + 1,
         );
         if !unique_visitors.contains_key(&entry.agent) {
-            unique_visitors.insert(entry.agent.clone(), HashSet::new());
+            unique_visitors.insert(entry.agent.clone(), DistinctCounter::new(config));
         }
         unique_visitors
             .get_mut(&entry.agent)
@@ -753,7 +1727,7 @@ fn get_user_agent_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml)
         lines.push(format!(
                 "<tr><td class=\"ss-user-agent\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
                 truncate_string(&agent, "user-agent", config, true),
-                unique_visitors.get(&agent).unwrap().len(),
+                format_distinct(unique_visitors[&agent].count(), config),
                 count,
                 format_percent(count as usize, entries.len()),
                 human_readable_bytes(bw[&agent]),
@@ -767,30 +1741,124 @@ fn get_user_agent_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml)
     return template.replace("{{rows}}", &lines.join(""));
 }
 
-fn get_pages_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
-    let mut lines: Vec<String> = Vec::new();
-    let mut unique: LinkedHashMap<String, (i32, &str)> = LinkedHashMap::new();
-    let mut bw: HashMap<String, usize> = HashMap::new();
-    for entry in entries {
-        let server = &entry.canonical_server_name;
-        if unique.contains_key(&entry.request) {
-            unique.insert(
-                entry.request.clone(),
-                (unique.get(&entry.request).unwrap().0 + 1, server),
-            );
-        } else {
-            unique.insert(entry.request.clone(), (1, server));
-        }
-        bw.insert(
-            entry.request.clone(),
+fn get_user_agent_group_table(
+    entries: &Vec<Entry>,
+    total_size: usize,
+    config: &Yaml,
+    title: &str,
+    header: &str,
+    group: fn(&UserAgent) -> &String,
+) -> String {
+    let parser = Parser::new();
+    let mut parsed: HashMap<&str, UserAgent> = HashMap::new();
+    return get_grouped_table(entries, total_size, config, title, header, |entry| {
+        if !parsed.contains_key(entry.agent.as_str()) {
+            parsed.insert(&entry.agent, parse_user_agent(&parser, &entry.agent));
+        }
+        return group(&parsed[entry.agent.as_str()]).clone();
+    });
+}
+
+fn get_geoip_table(
+    entries: &Vec<Entry>,
+    total_size: usize,
+    databases: &GeoIpDatabases,
+    config: &Yaml,
+    title: &str,
+    header: &str,
+    group: fn(GeoLocation) -> String,
+) -> String {
+    let reader = databases.city.as_ref();
+    if reader.is_none() {
+        return String::new();
+    }
+    let reader = reader.unwrap();
+    let mut locations: HashMap<&str, String> = HashMap::new();
+    return get_grouped_table(entries, total_size, config, title, header, |entry| {
+        if !locations.contains_key(entry.ip.as_str()) {
+            locations.insert(&entry.ip, group(lookup_location(reader, &entry.ip)));
+        }
+        return locations[entry.ip.as_str()].clone();
+    });
+}
+
+fn get_grouped_table<'a>(
+    entries: &'a Vec<Entry>,
+    total_size: usize,
+    config: &Yaml,
+    title: &str,
+    header: &str,
+    mut group: impl FnMut(&'a Entry) -> String,
+) -> String {
+    let mut unique_groups: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut unique_visitors: HashMap<String, DistinctCounter> = HashMap::new();
+    let mut bw: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        let key = group(entry);
+        unique_groups.insert(key.clone(), *unique_groups.get(&key).unwrap_or(&0i32) + 1);
+        if !unique_visitors.contains_key(&key) {
+            unique_visitors.insert(key.clone(), DistinctCounter::new(config));
+        }
+        unique_visitors.get_mut(&key).unwrap().insert(&entry.ip);
+        bw.insert(
+            key.clone(),
+            *bw.get(&key).unwrap_or(&0usize) + entry.size as usize,
+        );
+    }
+    unique_groups = sort_map(unique_groups);
+    let mut lines: Vec<String> = Vec::new();
+    for (key, count) in unique_groups {
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            key,
+            format_distinct(unique_visitors[&key].count(), config),
+            count,
+            format_percent(count as usize, entries.len()),
+            human_readable_bytes(bw[&key]),
+            format_percent(bw[&key], total_size)
+        ));
+    }
+    return format_table_vvb(title, header, &lines.join(""));
+}
+
+fn get_pages_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    let mut lines: Vec<String> = Vec::new();
+    let mut unique: LinkedHashMap<String, (i32, &str)> = LinkedHashMap::new();
+    let mut bw: HashMap<String, usize> = HashMap::new();
+    let include_assets = config["pages-table-include-assets"]
+        .as_bool()
+        .unwrap_or(false);
+    for entry in entries {
+        if !include_assets && !is_pageview(entry, &classification) {
+            continue;
+        }
+        let server = &entry.canonical_server_name;
+        if unique.contains_key(&entry.request) {
+            unique.insert(
+                entry.request.clone(),
+                (unique.get(&entry.request).unwrap().0 + 1, server),
+            );
+        } else {
+            unique.insert(entry.request.clone(), (1, server));
+        }
+        bw.insert(
+            entry.request.clone(),
             *bw.get(&entry.request).unwrap_or(&0usize) + entry.size as usize,
         );
     }
     unique = sort_map_tuple(unique);
+    let comparison = get_comparison(entries, config);
+    let changes = match &comparison {
+        Some(c) => c.count_hits(entries, |e| {
+            (include_assets || is_pageview(e, &classification)).then(|| e.request.clone())
+        }),
+        None => HashMap::new(),
+    };
     for (request, count) in unique {
         let split: Vec<&str> = request.split(" ").collect();
         lines.push(format!(
-                "<tr><td>{}</td><td>{}</td><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                "<tr><td>{}</td><td>{}</td><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>\n",
                 count.1,
                 truncate_string(get_or_none(&split[0]).substring(0, 10), "request-method", config, true),
                 truncate_string((split.len() > 1).then(|| split[1]).unwrap_or("(none)"), "request-url", config, true),
@@ -799,14 +1867,335 @@ fn get_pages_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> St
                 format_percent(count.0 as usize, entries.len()),
                 human_readable_bytes(bw[&request]),
                 format_percent(bw[&request], total_size),
-                human_readable_bytes((bw[&request] / entries.len()) as usize)
+                human_readable_bytes((bw[&request] / entries.len()) as usize),
+                format_change_cell(&comparison, &changes, &request)
             ));
     }
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/pages-table.html")).unwrap());
+    return template
+        .replace("{{change-header}}", &get_change_header(&comparison))
+        .replace("{{rows}}", &lines.join(""));
+}
+
+fn get_routes_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    // The number of raw URLs listed under each route
+    let drilldown_rows = 25;
+    let rules = get_route_rules(config);
+    let include_assets = config["pages-table-include-assets"]
+        .as_bool()
+        .unwrap_or(false);
+    let has_latency = config["log-format"].as_str().unwrap_or("").contains("%D");
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut bw: HashMap<String, usize> = HashMap::new();
+    let mut latency: HashMap<String, i64> = HashMap::new();
+    let mut urls: HashMap<String, LinkedHashMap<String, i32>> = HashMap::new();
+    for entry in entries {
+        if !include_assets && !is_pageview(entry, &classification) {
+            continue;
+        }
+        let url = get_request_path(entry);
+        let route = normalize_route(&rules, url);
+        unique.insert(route.clone(), *unique.get(&route).unwrap_or(&0i32) + 1);
+        bw.insert(
+            route.clone(),
+            *bw.get(&route).unwrap_or(&0usize) + entry.size as usize,
+        );
+        latency.insert(
+            route.clone(),
+            *latency.get(&route).unwrap_or(&0i64) + entry.time_to_serve_us,
+        );
+        if !urls.contains_key(&route) {
+            urls.insert(route.clone(), LinkedHashMap::new());
+        }
+        let route_urls = urls.get_mut(&route).unwrap();
+        route_urls.insert(url.to_string(), *route_urls.get(url).unwrap_or(&0i32) + 1);
+    }
+    unique = sort_map(unique);
+    let mut lines: Vec<String> = Vec::new();
+    for (route, count) in unique {
+        let route_urls = sort_map(urls.remove(&route).unwrap());
+        let mut drilldown: Vec<String> = route_urls
+            .iter()
+            .take(drilldown_rows)
+            .map(|(url, c)| {
+                format!(
+                    "{} ({})",
+                    truncate_string(&escape_html(url), "request-url", config, true),
+                    c
+                )
+            })
+            .collect();
+        if route_urls.len() > drilldown_rows {
+            drilldown.push(format!("and {} more", route_urls.len() - drilldown_rows));
+        }
+        lines.push(format!(
+            "<tr><td class=\"ss-page-url\"><details><summary>{}</summary>{}</details></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            truncate_string(&escape_html(&route), "request-url", config, true),
+            drilldown.join("<br>"),
+            route_urls.len(),
+            count,
+            format_percent(count as usize, entries.len()),
+            human_readable_bytes(bw[&route]),
+            format_percent(bw[&route], total_size),
+            if has_latency {
+                format!("{} &mu;s", latency[&route] / count as i64)
+            } else {
+                String::from("-")
+            }
+        ));
+    }
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/routes-table.html")).unwrap());
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+fn get_assets_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    if !config["include-assets-table"].as_bool().unwrap_or(true) {
+        return String::new();
+    }
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut bw: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        if is_pageview(entry, &classification) {
+            continue;
+        }
+        let asset_type = get_asset_type(entry, &classification).to_string();
+        unique.insert(
+            asset_type.clone(),
+            *unique.get(&asset_type).unwrap_or(&0i32) + 1,
+        );
+        bw.insert(
+            asset_type.clone(),
+            *bw.get(&asset_type).unwrap_or(&0usize) + entry.size as usize,
+        );
+    }
+    unique = sort_map(unique);
+    let mut lines: Vec<String> = Vec::new();
+    for (asset_type, count) in unique {
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            asset_type,
+            count,
+            format_percent(count as usize, entries.len()),
+            human_readable_bytes(bw[&asset_type]),
+            format_percent(bw[&asset_type], total_size),
+            human_readable_bytes(bw[&asset_type] / count as usize)
+        ));
+    }
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/assets-table.html")).unwrap());
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+fn get_entry_pages_table(entries: &Vec<Entry>, sessions: &Vec<Session>, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut bounces: HashMap<String, i32> = HashMap::new();
+    let mut total = 0;
+    for session in sessions {
+        let pages = get_session_pageviews(session, entries, &classification);
+        if pages.is_empty() {
+            continue;
+        }
+        let url = get_request_path(&entries[pages[0]]).to_string();
+        unique.insert(url.clone(), *unique.get(&url).unwrap_or(&0i32) + 1);
+        if pages.len() == 1 {
+            bounces.insert(url.clone(), *bounces.get(&url).unwrap_or(&0i32) + 1);
+        }
+        total += 1;
+    }
+    unique = sort_map(unique);
+    let mut lines: Vec<String> = Vec::new();
+    for (url, count) in unique {
+        let bounce_count = *bounces.get(&url).unwrap_or(&0i32);
+        lines.push(format!(
+            "<tr><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            truncate_string(&escape_html(&url), "request-url", config, true),
+            count,
+            format_percent(count as usize, total),
+            bounce_count,
+            format_percent(bounce_count as usize, count as usize)
+        ));
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/entry-pages-table.html")).unwrap(),
+    );
     return template.replace("{{rows}}", &lines.join(""));
 }
 
+fn get_exit_pages_table(entries: &Vec<Entry>, sessions: &Vec<Session>, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut pageviews: HashMap<String, i32> = HashMap::new();
+    let mut total = 0;
+    for session in sessions {
+        let pages = get_session_pageviews(session, entries, &classification);
+        if pages.is_empty() {
+            continue;
+        }
+        for i in &pages {
+            let url = get_request_path(&entries[*i]).to_string();
+            pageviews.insert(url.clone(), *pageviews.get(&url).unwrap_or(&0i32) + 1);
+        }
+        let url = get_request_path(&entries[pages[pages.len() - 1]]).to_string();
+        unique.insert(url.clone(), *unique.get(&url).unwrap_or(&0i32) + 1);
+        total += 1;
+    }
+    unique = sort_map(unique);
+    let mut lines: Vec<String> = Vec::new();
+    for (url, count) in unique {
+        lines.push(format!(
+            "<tr><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            truncate_string(&escape_html(&url), "request-url", config, true),
+            count,
+            format_percent(count as usize, total),
+            pageviews[&url],
+            format_percent(count as usize, pageviews[&url] as usize)
+        ));
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/exit-pages-table.html")).unwrap(),
+    );
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+fn get_top_paths_table(entries: &Vec<Entry>, sessions: &Vec<Session>, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    let max_length = config["top-paths-max-length"].as_i64().unwrap_or(4).max(2) as usize;
+    let rows = config["top-paths-rows"].as_i64().unwrap_or(20) as usize;
+    let mut lines: Vec<String> = Vec::new();
+    for length in 2..(max_length + 1) {
+        let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+        let mut total = 0;
+        for session in sessions {
+            let mut pages: Vec<&str> = get_session_pageviews(session, entries, &classification)
+                .iter()
+                .map(|i| get_request_path(&entries[*i]))
+                .collect();
+            // Reloads of the same page aren't steps
+            pages.dedup();
+            if pages.len() < length {
+                continue;
+            }
+            total += 1;
+            // Each path is counted once per visit
+            let mut seen: HashSet<String> = HashSet::new();
+            for window in pages.windows(length) {
+                let path: Vec<String> = window
+                    .iter()
+                    .map(|p| truncate_string(&escape_html(p), "request-url", config, true))
+                    .collect();
+                seen.insert(path.join(" &rarr; "));
+            }
+            for path in seen {
+                unique.insert(path.clone(), *unique.get(&path).unwrap_or(&0i32) + 1);
+            }
+        }
+        unique = sort_map(unique);
+        for (path, count) in unique.iter().take(rows) {
+            lines.push(format!(
+                "<tr><td>{}</td><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td></tr>\n",
+                length,
+                path,
+                count,
+                format_percent(*count as usize, total)
+            ));
+        }
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/top-paths-table.html")).unwrap(),
+    );
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+// Lists every request made by the most active visitors, split into visits.
+// The visitors are chosen the same way as the rows of the IP address table
+// so that its links line up.
+fn get_visitor_timelines(entries: &Vec<Entry>, sessions: &Vec<Session>, config: &Yaml) -> String {
+    let classification = get_page_classification(config);
+    let timelines = get_timeline_ips(entries, config);
+    if timelines.is_empty() {
+        return String::new();
+    }
+    let mut visitor_sessions: HashMap<String, Vec<&Session>> = HashMap::new();
+    for session in sessions {
+        let ip = anonymize_ip(&session.ip, config);
+        if !visitor_sessions.contains_key(&ip) {
+            visitor_sessions.insert(ip.clone(), Vec::new());
+        }
+        visitor_sessions.get_mut(&ip).unwrap().push(session);
+    }
+    let mut output: Vec<String> = Vec::new();
+    for ip in &timelines {
+        let mut lines: Vec<String> = Vec::new();
+        for (i, session) in visitor_sessions[ip].iter().enumerate() {
+            lines.push(format!("<tr><th colspan=\"5\">Visit {}</th></tr>\n", i + 1));
+            for index in &session.entries {
+                let entry = &entries[*index];
+                lines.push(format!(
+                    "<tr{}><td>{}</td><td class=\"ss-page-url\">{}</td><td>{}</td><td class=\"ss-referer\">{}</td><td>{}</td></tr>\n",
+                    (!is_pageview(entry, &classification)).then(|| " class=\"ss-asset\"").unwrap_or(""),
+                    format_date_config(&entry.time, config),
+                    truncate_string(&escape_html(&entry.request), "request-url", config, true),
+                    entry.response,
+                    truncate_string(&escape_html(&entry.referer), "referer", config, true),
+                    is_pageview(entry, &classification).then(|| "Page").unwrap_or("Asset")
+                ));
+            }
+        }
+        let template = String::from(
+            std::str::from_utf8(include_bytes!("templates/visitor-timeline.html")).unwrap(),
+        );
+        output.push(
+            template
+                .replace("{{ip}}", ip)
+                .replace("{{rows}}", &lines.join("")),
+        );
+    }
+    return format!("<h2>Visitor Timelines</h2>\n{}", output.join(""));
+}
+
+// The addresses with the most hits get a timeline. If the IP address table is
+// limited by max-rows, they're chosen from the same rows so that its links to
+// the timelines always have a target.
+fn get_timeline_ips(entries: &Vec<Entry>, config: &Yaml) -> Vec<String> {
+    let timelines = config["visitor-timelines"].as_i64().unwrap_or(0).max(0) as usize;
+    if timelines == 0 {
+        return Vec::new();
+    }
+    let top = get_top_keys(entries, "ip-table", config, |e| anonymize_ip(&e.ip, config));
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    for entry in entries {
+        let ip = anonymize_ip(&entry.ip, config);
+        if top.as_ref().is_some_and(|t| !t.contains(&ip)) {
+            continue;
+        }
+        unique.insert(ip.clone(), *unique.get(&ip).unwrap_or(&0i32) + 1);
+    }
+    return sort_map(unique)
+        .into_iter()
+        .take(timelines)
+        .map(|(ip, _)| ip)
+        .collect();
+}
+
+// The pageviews within a visit, in order, leaving out requests for assets.
+fn get_session_pageviews(
+    session: &Session,
+    entries: &Vec<Entry>,
+    classification: &PageClassification,
+) -> Vec<usize> {
+    return session
+        .entries
+        .iter()
+        .filter(|i| is_pageview(&entries[**i], classification))
+        .copied()
+        .collect();
+}
+
 fn get_files_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
     let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
     let mut bw: HashMap<String, usize> = HashMap::new();
@@ -851,7 +2240,14 @@ fn get_files_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> St
 
 fn get_queries_table(entries: &Vec<Entry>, config: &Yaml) -> String {
     let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let top = get_top_keys(entries, "queries-table", config, |e| e.query.clone());
+    let mut other = (0usize, new_other_counter(config));
     for entry in entries {
+        if top.as_ref().is_some_and(|t| !t.contains(&entry.query)) {
+            other.0 += 1;
+            other.1.insert(&entry.query);
+            continue;
+        }
         unique.insert(
             entry.query.clone(),
             *unique.get(&entry.query).unwrap_or(&0i32) + 1,
@@ -867,6 +2263,14 @@ fn get_queries_table(entries: &Vec<Entry>, config: &Yaml) -> String {
             format_percent(count as usize, entries.len()),
         ));
     }
+    if other.0 > 0 {
+        lines.push(format!(
+            "<tr><th>{}</th><th>{}</th><th>{}</th></tr>\n",
+            format_other_label(&other.1),
+            other.0,
+            format_percent(other.0, entries.len()),
+        ));
+    }
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/queries-table.html")).unwrap());
     return template.replace("{{rows}}", &lines.join(""));
@@ -875,7 +2279,15 @@ fn get_queries_table(entries: &Vec<Entry>, config: &Yaml) -> String {
 fn get_referers_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
     let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
     let mut bw: HashMap<String, usize> = HashMap::new();
+    let top = get_top_keys(entries, "referers-table", config, |e| e.referer.clone());
+    let mut other = (0usize, 0usize, new_other_counter(config));
     for entry in entries {
+        if top.as_ref().is_some_and(|t| !t.contains(&entry.referer)) {
+            other.0 += 1;
+            other.1 += entry.size as usize;
+            other.2.insert(&entry.referer);
+            continue;
+        }
         unique.insert(
             entry.referer.clone(),
             *unique.get(&entry.referer).unwrap_or(&0i32) + 1,
@@ -886,23 +2298,42 @@ fn get_referers_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) ->
         );
     }
     unique = sort_map(unique);
+    let comparison = get_comparison(entries, config);
+    let changes = match &comparison {
+        Some(c) => c.count_hits(entries, |e| Some(e.referer.clone())),
+        None => HashMap::new(),
+    };
     let mut lines: Vec<String> = Vec::new();
     for (referer, count) in unique {
         lines.push(format!(
-            "<tr><td class=\"ss-referer\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            "<tr><td class=\"ss-referer\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>\n",
             truncate_string(&referer, "referer", config, true),
             count,
             format_percent(count as usize, entries.len()),
             human_readable_bytes(bw[&referer]),
-            format_percent(bw[&referer], total_size)
+            format_percent(bw[&referer], total_size),
+            format_change_cell(&comparison, &changes, &referer)
+        ));
+    }
+    if other.0 > 0 {
+        lines.push(format!(
+            "<tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th>{}</tr>\n",
+            format_other_label(&other.2),
+            other.0,
+            format_percent(other.0, entries.len()),
+            human_readable_bytes(other.1),
+            format_percent(other.1, total_size),
+            comparison.is_some().then(|| "<th></th>").unwrap_or("")
         ));
     }
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/referers-table.html")).unwrap());
-    return template.replace("{{rows}}", &lines.join(""));
+    return template
+        .replace("{{change-header}}", &get_change_header(&comparison))
+        .replace("{{rows}}", &lines.join(""));
 }
 
-fn get_responses_table(entries: &Vec<Entry>, total_size: usize) -> String {
+fn get_responses_table(entries: &Vec<Entry>, total_size: usize, config: &Yaml) -> String {
     let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
     let mut bw: HashMap<String, usize> = HashMap::new();
     for entry in entries {
@@ -916,23 +2347,185 @@ fn get_responses_table(entries: &Vec<Entry>, total_size: usize) -> String {
         );
     }
     unique = sort_map(unique);
+    let comparison = get_comparison(entries, config);
+    let changes = match &comparison {
+        Some(c) => c.count_hits(entries, |e| Some(e.response.clone())),
+        None => HashMap::new(),
+    };
     let mut lines: Vec<String> = Vec::new();
     for (response, count) in unique {
         lines.push(format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>\n",
             response,
             count,
             format_percent(count as usize, entries.len()),
             human_readable_bytes(bw[&response]),
-            format_percent(bw[&response], total_size)
+            format_percent(bw[&response], total_size),
+            format_change_cell(&comparison, &changes, &response)
         ));
     }
     let template = String::from(
         std::str::from_utf8(include_bytes!("templates/responses-table.html")).unwrap(),
     );
+    return template
+        .replace("{{change-header}}", &get_change_header(&comparison))
+        .replace("{{rows}}", &lines.join(""));
+}
+
+fn get_errors_table(entries: &Vec<Entry>, title: &str, header: &str, date_format: &str) -> String {
+    // (hits, client errors, server errors) for each period
+    let mut periods: BTreeMap<String, (usize, usize, usize)> = BTreeMap::new();
+    for entry in entries {
+        let period = periods
+            .entry(format_date(&entry.time, date_format))
+            .or_insert((0, 0, 0));
+        period.0 += 1;
+        if entry.response.starts_with('4') {
+            period.1 += 1;
+        } else if entry.response.starts_with('5') {
+            period.2 += 1;
+        }
+    }
+    let mut lines: Vec<String> = Vec::new();
+    for (period, (hits, client, server)) in periods {
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            period,
+            hits,
+            client,
+            format_percent(client, hits),
+            server,
+            format_percent(server, hits)
+        ));
+    }
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/errors-table.html")).unwrap());
+    return template
+        .replace("{{title}}", title)
+        .replace("{{key}}", header)
+        .replace("{{rows}}", &lines.join(""));
+}
+
+fn get_error_urls_table(entries: &Vec<Entry>, config: &Yaml) -> String {
+    let rows = config["error-urls-rows"].as_i64().unwrap_or(10) as usize;
+    let mut codes: BTreeMap<String, LinkedHashMap<String, i32>> = BTreeMap::new();
+    for entry in entries {
+        if !entry.response.starts_with('4') && !entry.response.starts_with('5') {
+            continue;
+        }
+        let urls = codes.entry(entry.response.clone()).or_default();
+        let url = get_request_path(entry);
+        urls.insert(url.to_string(), *urls.get(url).unwrap_or(&0i32) + 1);
+    }
+    let mut lines: Vec<String> = Vec::new();
+    for (code, urls) in codes {
+        let total: i32 = urls.values().sum();
+        for (url, count) in sort_map(urls).into_iter().take(rows) {
+            lines.push(format!(
+                "<tr><td>{}</td><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td></tr>\n",
+                code,
+                truncate_string(&escape_html(&url), "request-url", config, true),
+                count,
+                format_percent(count as usize, total as usize)
+            ));
+        }
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/error-urls-table.html")).unwrap(),
+    );
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+// Lists the URLs that returned 404 along with the pages linking to them.
+// Referers on one of the hosts in internal-hosts or one of the server names
+// in the log are internal links that can be fixed directly.
+fn get_broken_links_table(entries: &Vec<Entry>, config: &Yaml) -> String {
+    let mut internal_hosts: HashSet<String> = HashSet::new();
+    for host in config["internal-hosts"].as_vec().unwrap_or(&Vec::new()) {
+        if let Some(host) = host.as_str() {
+            internal_hosts.insert(host.to_lowercase());
+        }
+    }
+    for entry in entries {
+        for name in [&entry.canonical_server_name, &entry.server_name] {
+            if !name.is_empty() && !name.eq("-") {
+                internal_hosts.insert(name.to_lowercase());
+            }
+        }
+    }
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut internal: HashMap<String, LinkedHashMap<String, i32>> = HashMap::new();
+    let mut external: HashMap<String, LinkedHashMap<String, i32>> = HashMap::new();
+    let mut direct: HashMap<String, i32> = HashMap::new();
+    for entry in entries {
+        if !entry.response.eq("404") {
+            continue;
+        }
+        let url = get_request_path(entry).to_string();
+        unique.insert(url.clone(), *unique.get(&url).unwrap_or(&0i32) + 1);
+        if entry.referer.is_empty() || entry.referer.eq("-") {
+            direct.insert(url.clone(), *direct.get(&url).unwrap_or(&0i32) + 1);
+            continue;
+        }
+        let host = get_referer_host(&entry.referer);
+        let referers = if internal_hosts.contains(&host) {
+            internal.entry(url).or_default()
+        } else {
+            external.entry(url).or_default()
+        };
+        referers.insert(
+            entry.referer.clone(),
+            *referers.get(&entry.referer).unwrap_or(&0i32) + 1,
+        );
+    }
+    unique = sort_map(unique);
+    let mut lines: Vec<String> = Vec::new();
+    for (url, count) in unique {
+        lines.push(format!(
+            "<tr><td class=\"ss-page-url\">{}</td><td>{}</td><td class=\"ss-referer\">{}</td><td class=\"ss-referer\">{}</td><td>{}</td></tr>\n",
+            truncate_string(&escape_html(&url), "request-url", config, true),
+            count,
+            format_referer_list(internal.remove(&url), config),
+            format_referer_list(external.remove(&url), config),
+            direct.get(&url).unwrap_or(&0i32)
+        ));
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/broken-links-table.html")).unwrap(),
+    );
     return template.replace("{{rows}}", &lines.join(""));
 }
 
+fn format_referer_list(referers: Option<LinkedHashMap<String, i32>>, config: &Yaml) -> String {
+    match referers {
+        Some(referers) => {
+            return sort_map(referers)
+                .iter()
+                .map(|(referer, count)| {
+                    format!(
+                        "{} ({})",
+                        truncate_string(&escape_html(referer), "referer", config, true),
+                        count
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("<br>");
+        }
+        None => return String::from("-"),
+    }
+}
+
+fn get_referer_host(referer: &str) -> String {
+    let without_scheme = referer.split_once("://").map(|r| r.1).unwrap_or(referer);
+    let authority = without_scheme.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or("");
+    // IPv6 addresses are bracketed so that their colons aren't taken for a port
+    if let Some(address) = host.strip_prefix('[') {
+        return address.split(']').next().unwrap_or("").to_lowercase();
+    }
+    return host.split(':').next().unwrap_or("").to_lowercase();
+}
+
 fn get_time_taken_table(entries: &Vec<Entry>) -> String {
     let mut sum = 0i64;
     let mut times: LinkedHashMap<String, i32> = LinkedHashMap::new();
@@ -994,6 +2587,501 @@ fn get_time_taken_table(entries: &Vec<Entry>) -> String {
         .replace("{{avg}}", &(sum as usize / entries.len()).to_string());
 }
 
+// The logged time taken keys, with %D first if both are present.
+fn get_latency_keys(config: &Yaml) -> Vec<&'static str> {
+    let log_format = config["log-format"].as_str().unwrap_or("");
+    return ["%D", "%T"]
+        .into_iter()
+        .filter(|k| log_format.contains(k))
+        .collect();
+}
+
+fn get_latency(entry: &Entry, key: &str) -> f64 {
+    if key.eq("%D") {
+        return entry.time_to_serve_us as f64;
+    }
+    return entry.time_to_serve_s as f64;
+}
+
+fn format_latency(value: f64, key: &str) -> String {
+    if key.eq("%D") {
+        return format!("{:.2} ms", value / 1000.0);
+    }
+    return format!("{:.0} s", value);
+}
+
+fn format_latency_row(label: &str, sketch: &QuantileSketch, key: &str) -> String {
+    return format!(
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+        label,
+        sketch.count(),
+        format_latency(sketch.quantile(0.5), key),
+        format_latency(sketch.quantile(0.9), key),
+        format_latency(sketch.quantile(0.95), key),
+        format_latency(sketch.quantile(0.99), key),
+        format_latency(sketch.max(), key)
+    );
+}
+
+fn format_latency_table(title: &str, header: &str, rows: &str) -> String {
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/latency-table.html")).unwrap());
+    return template
+        .replace("{{title}}", title)
+        .replace("{{key}}", header)
+        .replace("{{rows}}", rows);
+}
+
+fn get_route_sketches(
+    entries: &Vec<Entry>,
+    key: &str,
+    config: &Yaml,
+) -> LinkedHashMap<String, QuantileSketch> {
+    let rules = get_route_rules(config);
+    let mut routes: LinkedHashMap<String, QuantileSketch> = LinkedHashMap::new();
+    for entry in entries {
+        let route = normalize_route(&rules, get_request_path(entry));
+        if !routes.contains_key(&route) {
+            routes.insert(route.clone(), QuantileSketch::new());
+        }
+        routes.get_mut(&route).unwrap().add(get_latency(entry, key));
+    }
+    return routes;
+}
+
+fn get_latency_tables(entries: &Vec<Entry>, config: &Yaml) -> String {
+    let keys = get_latency_keys(config);
+    if keys.is_empty() {
+        return String::new();
+    }
+    // Per-day, per-route and per-status tables use the most precise measure
+    let key = keys[0];
+    let mut overall: Vec<String> = Vec::new();
+    for k in &keys {
+        let mut sketch = QuantileSketch::new();
+        for entry in entries {
+            sketch.add(get_latency(entry, k));
+        }
+        overall.push(format_latency_row(get_key_name(k), &sketch, k));
+    }
+    let mut days: BTreeMap<String, QuantileSketch> = BTreeMap::new();
+    let mut classes: BTreeMap<String, QuantileSketch> = BTreeMap::new();
+    for entry in entries {
+        let day = format_date(&entry.time, "%Y-%m-%d");
+        days.entry(day)
+            .or_insert_with(QuantileSketch::new)
+            .add(get_latency(entry, key));
+        let class = format!("{}xx", entry.response.substring(0, 1));
+        classes
+            .entry(class)
+            .or_insert_with(QuantileSketch::new)
+            .add(get_latency(entry, key));
+    }
+    let mut routes: Vec<(String, QuantileSketch)> = get_route_sketches(entries, key, config)
+        .into_iter()
+        .collect();
+    routes.sort_by(|a, b| b.1.count().cmp(&a.1.count()));
+
+    let mut output =
+        format_latency_table("Response Time Percentiles", "Measure", &overall.join(""));
+    output.push_str(&format_latency_table(
+        "Response Time by Day",
+        "Day",
+        &days
+            .iter()
+            .map(|(day, sketch)| format_latency_row(day, sketch, key))
+            .collect::<Vec<String>>()
+            .join(""),
+    ));
+    output.push_str(&format_latency_table(
+        "Response Time by Status Class",
+        "Status",
+        &classes
+            .iter()
+            .map(|(class, sketch)| format_latency_row(class, sketch, key))
+            .collect::<Vec<String>>()
+            .join(""),
+    ));
+    output.push_str(&format_latency_table(
+        "Response Time by Route",
+        "Route",
+        &routes
+            .iter()
+            .map(|(route, sketch)| {
+                format_latency_row(
+                    &truncate_string(&escape_html(route), "request-url", config, true),
+                    sketch,
+                    key,
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(""),
+    ));
+    return output;
+}
+
+fn get_slowest_endpoints_table(entries: &Vec<Entry>, config: &Yaml) -> String {
+    let keys = get_latency_keys(config);
+    let rows = config["slowest-endpoints-rows"].as_i64().unwrap_or(20) as usize;
+    // Routes with only a handful of requests are too noisy to rank
+    let min_requests = config["slowest-endpoints-min-requests"]
+        .as_i64()
+        .unwrap_or(10) as u64;
+    if keys.is_empty() || rows == 0 {
+        return String::new();
+    }
+    let key = keys[0];
+    let mut routes: Vec<(String, QuantileSketch)> = get_route_sketches(entries, key, config)
+        .into_iter()
+        .filter(|(_, sketch)| sketch.count() >= min_requests)
+        .collect();
+    routes.sort_by(|a, b| b.1.quantile(0.95).total_cmp(&a.1.quantile(0.95)));
+    let lines: Vec<String> = routes
+        .iter()
+        .take(rows)
+        .map(|(route, sketch)| {
+            format_latency_row(
+                &truncate_string(&escape_html(route), "request-url", config, true),
+                sketch,
+                key,
+            )
+        })
+        .collect();
+    return format_latency_table("Slowest Endpoints", "Route", &lines.join(""));
+}
+
+// Each SLO covers the requests whose path matches its filter. A request is
+// good if it was served within the threshold without a 5xx response. Apdex
+// uses the same threshold, with requests taking up to four times as long
+// counting as tolerable.
+fn get_slo_tables(entries: &Vec<Entry>, config: &Yaml) -> String {
+    let mut output = String::new();
+    for slo in config["slos"].as_vec().unwrap_or(&Vec::new()) {
+        let name = slo["name"].as_str().unwrap_or("SLO");
+        let pattern = slo["filter"].as_str().unwrap_or("");
+        let threshold = slo["threshold-ms"].as_i64().unwrap_or(500);
+        let target = slo["target"]
+            .as_f64()
+            .or(slo["target"].as_i64().map(|t| t as f64))
+            .unwrap_or(99.0);
+        let filter = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                eprintln!("error: Invalid filter for SLO {}: {}", name, e);
+                continue;
+            }
+        };
+        // (requests, good, satisfied, tolerating) for each day
+        let mut days: BTreeMap<String, (usize, usize, usize, usize)> = BTreeMap::new();
+        for entry in entries {
+            if !filter.is_match(get_request_path(entry)) {
+                continue;
+            }
+            let day = days
+                .entry(format_date(&entry.time, "%Y-%m-%d"))
+                .or_insert((0, 0, 0, 0));
+            day.0 += 1;
+            if entry.response.starts_with('5') {
+                continue;
+            }
+            // Compared in microseconds so that e.g. 300.9ms doesn't satisfy 300ms
+            if entry.time_to_serve_us <= threshold * 1000 {
+                day.1 += 1;
+                day.2 += 1;
+            } else if entry.time_to_serve_us <= threshold * 4000 {
+                day.3 += 1;
+            }
+        }
+        let mut lines: Vec<String> = Vec::new();
+        let mut total = (0usize, 0usize, 0usize, 0usize);
+        for (day, counts) in &days {
+            total = (
+                total.0 + counts.0,
+                total.1 + counts.1,
+                total.2 + counts.2,
+                total.3 + counts.3,
+            );
+            // The budget remaining is cumulative up to the end of each day
+            lines.push(format_slo_row(day, *counts, total, target));
+        }
+        lines.push(format_slo_row("Total", total, total, target).replace("td>", "th>"));
+        let template =
+            String::from(std::str::from_utf8(include_bytes!("templates/slo-table.html")).unwrap());
+        output.push_str(
+            &template
+                .replace("{{name}}", name)
+                .replace("{{target}}", &target.to_string())
+                .replace(
+                    "{{filter}}",
+                    &(!pattern.is_empty())
+                        .then(|| format!(" matching <code>{}</code>", pattern))
+                        .unwrap_or(String::new()),
+                )
+                .replace("{{threshold}}", &threshold.to_string())
+                .replace("{{rows}}", &lines.join("")),
+        );
+    }
+    return output;
+}
+
+fn format_slo_row(
+    label: &str,
+    counts: (usize, usize, usize, usize),
+    cumulative: (usize, usize, usize, usize),
+    target: f64,
+) -> String {
+    let (requests, good, satisfied, tolerating) = counts;
+    let allowed = cumulative.0 as f64 * (100.0 - target) / 100.0;
+    let bad = (cumulative.0 - cumulative.1) as f64;
+    let budget = if allowed > 0.0 {
+        format!("{:.2}%", (1.0 - bad / allowed) * 100.0)
+    } else {
+        String::from("-")
+    };
+    return format!(
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{}</td></tr>\n",
+        label,
+        requests,
+        good,
+        format_percent(good, requests),
+        (satisfied as f64 + tolerating as f64 / 2.0) / requests.max(1) as f64,
+        budget
+    );
+}
+
+fn get_security_findings_table(security: &SecurityReport, config: &Yaml) -> String {
+    let rows = config["security-findings-rows"].as_i64().unwrap_or(20) as usize;
+    let mut findings: Vec<&Finding> = security.findings.iter().collect();
+    let mut categories: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    for finding in &findings {
+        categories.insert(
+            finding.category.clone(),
+            *categories.get(&finding.category).unwrap_or(&0i32) + finding.hits,
+        );
+    }
+    findings.sort_by(|a, b| b.hits.cmp(&a.hits));
+    let mut lines: Vec<String> = Vec::new();
+    for (category, total) in sort_map(categories) {
+        let mut shown = 0;
+        let mut hidden = 0;
+        for finding in findings.iter().filter(|f| f.category.eq(&category)) {
+            if rows > 0 && shown >= rows {
+                hidden += 1;
+                continue;
+            }
+            shown += 1;
+            lines.push(format!(
+                "<tr><td>{}</td><td>{}</td><td class=\"ss-page-url\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&category),
+                anonymize_ip(&finding.ip, config),
+                truncate_string(&escape_html(&finding.url), "request-url", config, true),
+                finding.hits,
+                format_date_config(&finding.first_seen, config),
+                format_date_config(&finding.last_seen, config)
+            ));
+        }
+        let others = if hidden > 0 {
+            format!("and {} more", hidden)
+        } else {
+            String::new()
+        };
+        lines.push(format!(
+            "<tr><th>{}</th><th colspan=\"2\">{}</th><th>{}</th><th></th><th></th></tr>\n",
+            escape_html(&category),
+            others,
+            total
+        ));
+    }
+    if lines.is_empty() {
+        lines.push(String::from(
+            "<tr><td colspan=\"6\">No requests matched a security rule</td></tr>\n",
+        ));
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/security-findings-table.html")).unwrap(),
+    );
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+// Lists IP addresses with bursts of failed logins, along with the usernames
+// they tried. If usernames are logged, usernames tried from many addresses
+// are listed as well.
+fn get_brute_force_tables(
+    entries: &Vec<Entry>,
+    security: &SecurityReport,
+    config: &Yaml,
+) -> String {
+    let mut output = format_brute_force_table(
+        "Suspected Brute Force Sources",
+        "IP Address",
+        "Targeted Users",
+        &security.brute_force,
+        config,
+        |ip| anonymize_ip(ip, config),
+        escape_html,
+    );
+    if config["log-format"].as_str().unwrap_or("").contains("%u") {
+        output.push_str(&format_brute_force_table(
+            "Targeted Users",
+            "User",
+            "Source IP Addresses",
+            &get_brute_force(entries, config, true),
+            config,
+            escape_html,
+            |ip| anonymize_ip(ip, config),
+        ));
+    }
+    return output;
+}
+
+fn format_brute_force_table(
+    title: &str,
+    source_header: &str,
+    targets_header: &str,
+    sources: &Vec<BruteForce>,
+    config: &Yaml,
+    format_source: impl Fn(&str) -> String,
+    format_target: impl Fn(&str) -> String,
+) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for source in sources {
+        let mut targets: Vec<String> = source
+            .targets
+            .iter()
+            .take(10)
+            .map(|t| format_target(t))
+            .collect();
+        if source.targets.len() > 10 {
+            targets.push(format!("and {} more", source.targets.len() - 10));
+        }
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            format_source(&source.source),
+            source.failures,
+            source.peak,
+            format_date_config(&source.first_seen, config),
+            format_date_config(&source.last_seen, config),
+            get_or_none(&targets.join(", "))
+        ));
+    }
+    if lines.is_empty() {
+        lines.push(String::from(
+            "<tr><td colspan=\"6\">No brute force attempts were found</td></tr>\n",
+        ));
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/brute-force-table.html")).unwrap(),
+    );
+    return template
+        .replace("{{title}}", title)
+        .replace("{{source}}", source_header)
+        .replace("{{targets}}", targets_header)
+        .replace(
+            "{{window}}",
+            &config["brute-force-window"]
+                .as_i64()
+                .unwrap_or(10)
+                .max(1)
+                .to_string(),
+        )
+        .replace("{{rows}}", &lines.join(""));
+}
+
+fn get_top_talkers_table(
+    entries: &Vec<Entry>,
+    total_size: usize,
+    security: &SecurityReport,
+    config: &Yaml,
+) -> String {
+    let rows = config["top-talkers-rows"].as_i64().unwrap_or(20) as usize;
+    if rows == 0 {
+        return String::new();
+    }
+    let mut unique: LinkedHashMap<String, i32> = LinkedHashMap::new();
+    let mut bw: HashMap<&str, usize> = HashMap::new();
+    for entry in entries {
+        unique.insert(
+            entry.ip.clone(),
+            *unique.get(&entry.ip).unwrap_or(&0i32) + 1,
+        );
+        *bw.entry(&entry.ip).or_insert(0) += entry.size as usize;
+    }
+    let mut lines: Vec<String> = Vec::new();
+    for (ip, count) in sort_map(unique).into_iter().take(rows) {
+        let rate = &security.rates[&ip];
+        lines.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td></tr>\n",
+            anonymize_ip(&ip, config),
+            count,
+            format_percent(count as usize, entries.len()),
+            human_readable_bytes(bw[ip.as_str()]),
+            format_percent(bw[ip.as_str()], total_size),
+            rate.per_second,
+            rate.per_minute,
+            rate.active_minutes,
+            count as f64 / rate.active_minutes as f64
+        ));
+    }
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/top-talkers-table.html")).unwrap(),
+    );
+    return template.replace("{{rows}}", &lines.join(""));
+}
+
+// Suggests limits for tools such as mod_evasive from the peak rates of
+// ordinary visitors, i.e. those that didn't trigger a security rule or the
+// brute force detection.
+fn get_rate_limits_table(security: &SecurityReport, config: &Yaml) -> String {
+    let headroom = config["rate-limit-headroom"]
+        .as_f64()
+        .or(config["rate-limit-headroom"].as_i64().map(|h| h as f64))
+        .unwrap_or(2.0);
+    let mut per_second: Vec<usize> = Vec::new();
+    let mut per_minute: Vec<usize> = Vec::new();
+    for (ip, rate) in &security.rates {
+        if !security.abusive_ips.contains(ip) {
+            per_second.push(rate.per_second);
+            per_minute.push(rate.per_minute);
+        }
+    }
+    if per_second.is_empty() {
+        return String::new();
+    }
+    per_second.sort();
+    per_minute.sort();
+    // Nearest-rank percentile
+    let percentile = |values: &Vec<usize>, p: f64| -> usize {
+        let rank = ((p / 100.0) * values.len() as f64).ceil() as usize;
+        return values[rank.clamp(1, values.len()) - 1];
+    };
+    let mut lines: Vec<String> = Vec::new();
+    for p in [50.0, 90.0, 95.0, 99.0] {
+        lines.push(format!(
+            "<tr><td>{}th</td><td>{}</td><td>{}</td></tr>\n",
+            p,
+            percentile(&per_second, p),
+            percentile(&per_minute, p)
+        ));
+    }
+    lines.push(format!(
+        "<tr><td>Maximum</td><td>{}</td><td>{}</td></tr>\n",
+        per_second[per_second.len() - 1],
+        per_minute[per_minute.len() - 1]
+    ));
+    lines.push(format!(
+        "<tr><th>Recommended Limit</th><th>{}</th><th>{}</th></tr>\n",
+        (percentile(&per_second, 99.0) as f64 * headroom).ceil(),
+        (percentile(&per_minute, 99.0) as f64 * headroom).ceil()
+    ));
+    let template = String::from(
+        std::str::from_utf8(include_bytes!("templates/rate-limits-table.html")).unwrap(),
+    );
+    return template
+        .replace("{{visitors}}", &per_second.len().to_string())
+        .replace("{{headroom}}", &headroom.to_string())
+        .replace("{{rows}}", &lines.join(""));
+}
+
 fn get_full_log(entries: &Vec<Entry>, log_keys: &Vec<&str>, config: &Yaml) -> String {
     let mut header: Vec<String> = Vec::new();
     let mut lines: Vec<String> = Vec::new();
@@ -1038,19 +3126,21 @@ fn format_table_vvb_avg(
     rows: &str,
     date_key: &str,
     entries: &Vec<Entry>,
+    starts: &Vec<bool>,
+    config: &Yaml,
 ) -> String {
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/table-vvb-avg.html")).unwrap());
-    return template
+    let template = replace_period_stats(
+        template,
+        "visitors",
+        &get_period_visitors(entries, date_key, config),
+        &|v| format_distinct(v, config),
+    );
+    return format_period_stats(template, date_key, entries, starts, config)
         .replace("{{title}}", title)
         .replace("{{key}}", header)
-        .replace("{{rows}}", rows)
-        .replace("{{avg-visitors}}", &get_average_visitors(entries, date_key))
-        .replace("{{avg-visits}}", &get_average_visits(entries, date_key))
-        .replace(
-            "{{avg-bandwidth}}",
-            &get_average_bandwidth(entries, date_key),
-        );
+        .replace("{{rows}}", rows);
 }
 
 fn format_table_vb_avg(
@@ -1059,18 +3149,88 @@ fn format_table_vb_avg(
     rows: &str,
     date_key: &str,
     entries: &Vec<Entry>,
+    starts: &Vec<bool>,
+    config: &Yaml,
 ) -> String {
     let template =
         String::from(std::str::from_utf8(include_bytes!("templates/table-vb-avg.html")).unwrap());
+    return format_period_stats(template, date_key, entries, starts, config)
+        .replace("{{title}}", title)
+        .replace("{{key}}", header)
+        .replace("{{rows}}", rows);
+}
+
+// Fills in the average, median and peak visits, hits, pageviews and bandwidth
+// per period.
+fn format_period_stats(
+    mut template: String,
+    date_key: &str,
+    entries: &Vec<Entry>,
+    starts: &Vec<bool>,
+    config: &Yaml,
+) -> String {
+    let classification = get_page_classification(config);
+    template = replace_period_stats(
+        template,
+        "visits",
+        &get_period_totals(entries, date_key, |i, _| starts[i] as usize),
+        &|v| v.to_string(),
+    );
+    template = replace_period_stats(
+        template,
+        "hits",
+        &get_period_totals(entries, date_key, |_, _| 1),
+        &|v| v.to_string(),
+    );
+    template = replace_period_stats(
+        template,
+        "pageviews",
+        &get_period_totals(entries, date_key, |_, e| {
+            is_pageview(e, &classification) as usize
+        }),
+        &|v| v.to_string(),
+    );
+    return replace_period_stats(
+        template,
+        "bandwidth",
+        &get_period_totals(entries, date_key, |_, e| e.size as usize),
+        &human_readable_bytes,
+    );
+}
+
+// Replaces {{avg-name}}, {{median-name}} and {{peak-name}}. The peak period is
+// shown when hovering over the peak value.
+fn replace_period_stats(
+    template: String,
+    name: &str,
+    totals: &Vec<(String, usize)>,
+    format: &dyn Fn(usize) -> String,
+) -> String {
+    let mut values: Vec<usize> = totals.iter().map(|t| t.1).collect();
+    values.sort();
+    let mean = values.iter().sum::<usize>() / values.len().max(1);
+    let median = match values.len() {
+        0 => 0,
+        n if n % 2 == 0 => (values[n / 2 - 1] + values[n / 2]) / 2,
+        n => values[n / 2],
+    };
+    let peak = match totals.iter().rev().max_by_key(|t| t.1) {
+        Some((period, value)) => format!("<span title=\"{}\">{}</span>", period, format(*value)),
+        None => String::from("0"),
+    };
+    return template
+        .replace(&format!("{{{{avg-{}}}}}", name), &format(mean))
+        .replace(&format!("{{{{median-{}}}}}", name), &format(median))
+        .replace(&format!("{{{{peak-{}}}}}", name), &peak);
+}
+
+fn format_table_vvb(title: &str, header: &str, rows: &str) -> String {
+    let template =
+        String::from(std::str::from_utf8(include_bytes!("templates/table-vvb.html")).unwrap());
     return template
         .replace("{{title}}", title)
         .replace("{{key}}", header)
-        .replace("{{rows}}", rows)
-        .replace("{{avg-visits}}", &get_average_visits(entries, date_key))
-        .replace(
-            "{{avg-bandwidth}}",
-            &get_average_bandwidth(entries, date_key),
-        );
+        .replace("{{rows}}", rows);
 }
 
 fn format_table_vb(title: &str, header: &str, rows: &str) -> String {
@@ -1115,6 +3275,7 @@ fn get_key_name(key: &str) -> &str {
         "%I" => return "Bytes Received",
         "%O" => return "Bytes Sent",
         "%S" => return "Bytes Transferred",
+        "%{Content-Type}o" => return "Content Type",
         _ => return "?",
     }
 }
@@ -1123,13 +3284,13 @@ fn get_key_value(key: &str, entry: &Entry, config: &Yaml) -> String {
     return format!(
         "{}",
         match key {
-            "%a" => entry.client_ip.clone(),
+            "%a" => anonymize_ip(&entry.client_ip, config),
             "%A" => entry.local_ip.clone(),
             "%B" => human_readable_bytes(entry.size_excl_headers as usize),
             "%b" => human_readable_bytes(entry.size_incl_headers as usize),
             "%D" => entry.time_to_serve_us.to_string(),
             "%f" => entry.filename.clone(),
-            "%h" => entry.ip.clone(),
+            "%h" => anonymize_ip(&entry.ip, config),
             "%H" => entry.request_protocol.clone(),
             "%{Referer}i" => entry.referer.clone(),
             "%{User-Agent}i" => entry.agent.clone(),
@@ -1158,6 +3319,7 @@ fn get_key_value(key: &str, entry: &Entry, config: &Yaml) -> String {
             "%I" => human_readable_bytes(entry.bytes_received as usize),
             "%O" => human_readable_bytes(entry.size as usize),
             "%S" => human_readable_bytes(entry.bytes_transferred as usize),
+            "%{Content-Type}o" => entry.content_type.clone(),
             _ => String::from("?"),
         }
     );
@@ -1220,54 +3382,98 @@ fn get_or_else(key: &str, other: &str) -> String {
     }
 }
 
-fn get_average_visitors(entries: &Vec<Entry>, date_format: &str) -> String {
-    let mut unique: HashSet<&str> = HashSet::new();
-    let mut keys: HashSet<i32> = HashSet::new();
+// The number of distinct visitors in every period of the report's range.
+fn get_period_visitors(
+    entries: &Vec<Entry>,
+    date_format: &str,
+    config: &Yaml,
+) -> Vec<(String, usize)> {
+    let unique = get_visitor_rollup(&get_daily_visitors(entries, config), date_format, config);
+    return get_periods(entries, date_format)
+        .into_iter()
+        .map(|p| {
+            let count = unique.get(&p).map(|u| u.count()).unwrap_or(0);
+            (p, count)
+        })
+        .collect();
+}
+
+// Visitors are counted per day, and longer periods are counted by merging the
+// days within them.
+fn get_daily_visitors<'a>(
+    entries: &'a Vec<Entry>,
+    config: &Yaml,
+) -> BTreeMap<NaiveDate, DistinctCounter<'a>> {
+    let mut days: BTreeMap<NaiveDate, DistinctCounter> = BTreeMap::new();
     for entry in entries {
-        let key = format_date(&entry.time, date_format)
-            .parse::<i32>()
-            .unwrap();
-        keys.insert(key);
-        unique.insert(&entry.ip);
+        days.entry(entry.time.date_naive())
+            .or_insert_with(|| DistinctCounter::new(config))
+            .insert(&entry.ip);
     }
-    return (unique.len() / keys.len()).to_string();
+    return days;
 }
 
-fn get_average_visits(entries: &Vec<Entry>, date_format: &str) -> String {
-    let mut keys: HashSet<i32> = HashSet::new();
-    for entry in entries {
-        let key = format_date(&entry.time, date_format)
-            .parse::<i32>()
-            .unwrap();
-        keys.insert(key);
+fn get_visitor_rollup<'a>(
+    days: &BTreeMap<NaiveDate, DistinctCounter<'a>>,
+    date_format: &str,
+    config: &Yaml,
+) -> HashMap<String, DistinctCounter<'a>> {
+    let mut periods: HashMap<String, DistinctCounter> = HashMap::new();
+    for (day, visitors) in days {
+        periods
+            .entry(day.format(date_format).to_string())
+            .or_insert_with(|| DistinctCounter::new(config))
+            .merge(visitors);
     }
-    return (entries.len() / keys.len()).to_string();
+    return periods;
 }
 
-fn get_average_bandwidth(entries: &Vec<Entry>, date_format: &str) -> String {
-    let mut keys: HashMap<i32, usize> = HashMap::new();
-    for entry in entries {
-        let key = format_date(&entry.time, date_format)
-            .parse::<i32>()
-            .unwrap();
-        keys.insert(
-            key,
-            *keys.get(&key).unwrap_or(&0usize) + entry.size as usize,
+// Sums `value` over the entries in every period of the report's range.
+fn get_period_totals(
+    entries: &Vec<Entry>,
+    date_format: &str,
+    value: impl Fn(usize, &Entry) -> usize,
+) -> Vec<(String, usize)> {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let key = format_date(&entry.time, date_format);
+        totals.insert(
+            key.clone(),
+            *totals.get(&key).unwrap_or(&0usize) + value(i, entry),
         );
     }
-    let mut sum: usize = 0;
-    for (_key, size) in &keys {
-        sum += size;
+    return get_periods(entries, date_format)
+        .into_iter()
+        .map(|p| {
+            let total = *totals.get(&p).unwrap_or(&0usize);
+            (p, total)
+        })
+        .collect();
+}
+
+// Every period from the first entry to the last, in order. The format must
+// not be more precise than a day (e.g. "%Y-%m" for months).
+fn get_periods(entries: &Vec<Entry>, date_format: &str) -> Vec<String> {
+    let mut periods: Vec<String> = Vec::new();
+    let first = entries.iter().map(|e| e.time.date_naive()).min();
+    let last = entries.iter().map(|e| e.time.date_naive()).max();
+    if first.is_none() {
+        return periods;
+    }
+    let mut day: NaiveDate = first.unwrap();
+    while day <= last.unwrap() {
+        let period = day.format(date_format).to_string();
+        // Periods are contiguous, so only the last one needs checking
+        if periods.last() != Some(&period) {
+            periods.push(period);
+        }
+        day = day.succ_opt().unwrap();
     }
-    return human_readable_bytes((sum / keys.len()) as usize);
+    return periods;
 }
 
 fn format_date(date: &DateTime<FixedOffset>, format: &str) -> String {
-    return Local
-        .from_local_datetime(&date.naive_local())
-        .unwrap()
-        .format(&format)
-        .to_string();
+    return date.format(&format).to_string();
 }
 
 fn format_date_config(date: &DateTime<FixedOffset>, config: &Yaml) -> String {
@@ -1291,6 +3497,17 @@ fn get_connection_status(status: char) -> String {
     }
 }
 
+// Request data is attacker-controlled (e.g. GET /<script>), so it has to be
+// escaped before it is placed in the report.
+fn escape_html(s: &str) -> String {
+    return s
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;");
+}
+
 fn truncate_string(s: &str, key: &str, config: &Yaml, or_none: bool) -> String {
     let truncate = config["truncate"][key].as_i64().unwrap_or(0) as usize;
     if truncate != 0usize && s.len() > truncate {
@@ -0,0 +1,63 @@
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use yaml_rust::Yaml;
+
+pub fn is_ip_privacy_enabled(config: &Yaml) -> bool {
+    return !config["ip-privacy"]
+        .as_str()
+        .unwrap_or("none")
+        .eq_ignore_ascii_case("none");
+}
+
+// A hash made without a secret key can be reversed by hashing every possible
+// address, so hash mode can't be used without one.
+pub fn check_ip_privacy(config: &Yaml) -> bool {
+    let hash = config["ip-privacy"]
+        .as_str()
+        .unwrap_or("none")
+        .eq_ignore_ascii_case("hash");
+    if hash && config["ip-hash-key"].as_str().unwrap_or("").is_empty() {
+        eprintln!("error: ip-hash-key must be set to a secret key when ip-privacy is set to hash");
+        return false;
+    }
+    return true;
+}
+
+// Returns the form of an address that may appear in the report. Counting is
+// always done on the full address, so this only needs to be applied to output.
+pub fn anonymize_ip(ip: &str, config: &Yaml) -> String {
+    match config["ip-privacy"]
+        .as_str()
+        .unwrap_or("none")
+        .to_lowercase()
+        .as_str()
+    {
+        "truncate" => return truncate_ip(ip),
+        "hash" => return hash_ip(ip, config),
+        _ => return ip.to_string(),
+    }
+}
+
+fn truncate_ip(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => {
+            let o = v4.octets();
+            return Ipv4Addr::new(o[0], o[1], o[2], 0).to_string();
+        }
+        Ok(IpAddr::V6(v6)) => {
+            let s = v6.segments();
+            return Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0).to_string();
+        }
+        // Not an address (e.g. a hostname when HostnameLookups is on), so
+        // there is nothing sensible to truncate
+        Err(_) => return String::from("(hidden)"),
+    }
+}
+
+fn hash_ip(ip: &str, config: &Yaml) -> String {
+    let key = config["ip-hash-key"].as_str().unwrap_or("");
+    let mac = hmac_sha256::HMAC::mac(ip.as_bytes(), key.as_bytes());
+    let hex: Vec<String> = mac[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    return hex.join("");
+}
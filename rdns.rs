@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use yaml_rust::Yaml;
+
+// Resolves the hostname of each address, consulting the on-disk cache first.
+// Addresses without a PTR record are cached as well so they aren't retried
+// until their entry expires.
+pub fn resolve_hostnames(ips: &Vec<&str>, config: &Yaml) -> HashMap<String, String> {
+    let resolver = config["dns-resolver"]
+        .as_str()
+        .unwrap_or("1.1.1.1:53")
+        .parse::<SocketAddr>();
+    if resolver.is_err() {
+        eprintln!(
+            "error: Invalid DNS resolver address: {}",
+            config["dns-resolver"].as_str().unwrap_or("?")
+        );
+        return HashMap::new();
+    }
+    let resolver = resolver.unwrap();
+    // Capped at a minute so that the deadline can't overflow
+    let timeout = Duration::from_millis(
+        config["dns-timeout"]
+            .as_i64()
+            .unwrap_or(1000)
+            .clamp(1, 60000) as u64,
+    );
+    let ttl = config["dns-cache-ttl"].as_i64().unwrap_or(86400).max(0) as u64;
+    let cache_path =
+        shellexpand::tilde(config["dns-cache-file"].as_str().unwrap_or("")).to_string();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut cache = read_cache(&cache_path);
+    let mut hostnames: HashMap<String, String> = HashMap::new();
+    let mut changed = false;
+    for ip in ips {
+        if let Some((hostname, resolved)) = cache.get(*ip) {
+            if now.saturating_sub(*resolved) < ttl {
+                hostnames.insert(ip.to_string(), hostname.clone());
+                continue;
+            }
+        }
+        let hostname = lookup_ptr(ip, resolver, timeout);
+        if hostname.is_none() {
+            // Lookup failed outright (e.g. timeout), so don't cache anything
+            continue;
+        }
+        let hostname = hostname.unwrap();
+        cache.insert(ip.to_string(), (hostname.clone(), now));
+        hostnames.insert(ip.to_string(), hostname);
+        changed = true;
+    }
+    if changed && !cache_path.is_empty() {
+        write_cache(&cache_path, &cache, now, ttl);
+    }
+    return hostnames;
+}
+
+fn read_cache(path: &str) -> HashMap<String, (String, u64)> {
+    let mut cache: HashMap<String, (String, u64)> = HashMap::new();
+    if path.is_empty() {
+        return cache;
+    }
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    for line in contents.lines() {
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() != 3 {
+            continue;
+        }
+        if let Ok(resolved) = parts[2].parse::<u64>() {
+            cache.insert(parts[0].to_string(), (parts[1].to_string(), resolved));
+        }
+    }
+    return cache;
+}
+
+fn write_cache(path: &str, cache: &HashMap<String, (String, u64)>, now: u64, ttl: u64) {
+    let mut lines: Vec<String> = Vec::new();
+    for (ip, (hostname, resolved)) in cache {
+        if now.saturating_sub(*resolved) < ttl {
+            lines.push(format!("{}\t{}\t{}\n", ip, hostname, resolved));
+        }
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent).unwrap_or(());
+    }
+    if let Err(e) = fs::write(path, lines.join("")) {
+        eprintln!("error: Unable to write DNS cache to {}: {}", path, e);
+    }
+}
+
+// Returns Some("") if the server answered but had no PTR record, and None if
+// the lookup failed (e.g. timeout or SERVFAIL) so that nothing is cached.
+fn lookup_ptr(ip: &str, resolver: SocketAddr, timeout: Duration) -> Option<String> {
+    let address = ip.parse::<IpAddr>().ok()?;
+    let bind = if resolver.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).ok()?;
+    let id = (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0)
+        & 0xFFFF) as u16;
+    socket
+        .send_to(&build_query(id, &get_ptr_name(address)), resolver)
+        .ok()?;
+    let mut buffer = [0u8; 512];
+    // Stray packets shouldn't extend the timeout
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())?;
+        socket.set_read_timeout(Some(remaining)).ok()?;
+        let (length, from) = socket.recv_from(&mut buffer).ok()?;
+        if from != resolver || length < 12 || u16::from_be_bytes([buffer[0], buffer[1]]) != id {
+            continue;
+        }
+        return parse_response(&buffer[..length]);
+    }
+}
+
+fn get_ptr_name(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            return format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0]);
+        }
+        IpAddr::V6(v6) => {
+            let mut nibbles: Vec<String> = Vec::new();
+            for byte in v6.octets().iter().rev() {
+                nibbles.push(format!("{:x}", byte & 0x0F));
+                nibbles.push(format!("{:x}", byte >> 4));
+            }
+            return format!("{}.ip6.arpa", nibbles.join("."));
+        }
+    }
+}
+
+fn build_query(id: u16, name: &str) -> Vec<u8> {
+    let mut packet: Vec<u8> = Vec::new();
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Standard query with recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    // QTYPE PTR, QCLASS IN
+    packet.extend_from_slice(&[0x00, 0x0C, 0x00, 0x01]);
+    return packet;
+}
+
+// Only NOERROR and NXDOMAIN are answers; any other response code or a
+// malformed reply is treated as a failed lookup.
+fn parse_response(packet: &[u8]) -> Option<String> {
+    let rcode = packet[3] & 0x0F;
+    if rcode == 3 {
+        return Some(String::new());
+    }
+    if rcode != 0 {
+        return None;
+    }
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+    let answers = u16::from_be_bytes([packet[6], packet[7]]);
+    let mut offset = 12usize;
+    for _ in 0..questions {
+        offset = skip_name(packet, offset)? + 4;
+    }
+    for _ in 0..answers {
+        offset = skip_name(packet, offset)?;
+        let header = packet.get(offset..offset + 10)?;
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        offset += 10;
+        if record_type == 12 {
+            return read_name(packet, offset);
+        }
+        offset += length;
+    }
+    return Some(String::new());
+}
+
+fn skip_name(packet: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(offset)? as usize;
+        if length == 0 {
+            return Some(offset + 1);
+        }
+        if length & 0xC0 == 0xC0 {
+            return Some(offset + 2);
+        }
+        offset += length + 1;
+    }
+}
+
+fn read_name(packet: &[u8], mut offset: usize) -> Option<String> {
+    let mut labels: Vec<String> = Vec::new();
+    // Bounds the number of labels and compression pointers followed
+    for _ in 0..128 {
+        let length = *packet.get(offset)? as usize;
+        if length == 0 {
+            return Some(labels.join("."));
+        }
+        if length & 0xC0 == 0xC0 {
+            offset = ((length & 0x3F) << 8) | *packet.get(offset + 1)? as usize;
+            continue;
+        }
+        let label = packet.get(offset + 1..offset + 1 + length)?;
+        // Hostnames end up in the report and the cache file, so anything
+        // outside of the usual hostname characters is replaced
+        labels.push(
+            label
+                .iter()
+                .map(|b| match *b as char {
+                    c @ ('a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_') => c,
+                    _ => '?',
+                })
+                .collect(),
+        );
+        offset += length + 1;
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use yaml_rust::YamlLoader;
+
+    // Builds a reply to a query with the given response code and, if a
+    // hostname is given, a single PTR answer pointing back at the question.
+    fn build_reply(query: &[u8], rcode: u8, hostname: Option<&str>) -> Vec<u8> {
+        let mut reply = query.to_vec();
+        reply[2] = 0x81;
+        reply[3] = 0x80 | rcode;
+        if let Some(hostname) = hostname {
+            reply[7] = 1;
+            reply.extend_from_slice(&[0xC0, 0x0C, 0x00, 0x0C, 0x00, 0x01, 0, 0, 0x0E, 0x10]);
+            let mut name: Vec<u8> = Vec::new();
+            for label in hostname.split('.') {
+                name.push(label.len() as u8);
+                name.extend_from_slice(label.as_bytes());
+            }
+            name.push(0);
+            reply.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            reply.extend_from_slice(&name);
+        }
+        return reply;
+    }
+
+    #[test]
+    fn builds_ptr_query() {
+        let query = build_query(0x1234, &get_ptr_name("192.0.2.1".parse().unwrap()));
+        assert_eq!(&query[..2], &[0x12, 0x34]);
+        assert_eq!(&query[12..22], b"\x011\x012\x010\x03192");
+        assert!(query.ends_with(b"\x07in-addr\x04arpa\x00\x00\x0C\x00\x01"));
+    }
+
+    #[test]
+    fn parses_response_codes() {
+        let query = build_query(1, "1.2.0.192.in-addr.arpa");
+        assert_eq!(
+            parse_response(&build_reply(&query, 0, Some("host.example.com"))),
+            Some(String::from("host.example.com"))
+        );
+        assert_eq!(
+            parse_response(&build_reply(&query, 0, None)),
+            Some(String::new())
+        );
+        assert_eq!(
+            parse_response(&build_reply(&query, 3, None)),
+            Some(String::new())
+        );
+        // SERVFAIL and REFUSED
+        assert_eq!(parse_response(&build_reply(&query, 2, None)), None);
+        assert_eq!(parse_response(&build_reply(&query, 5, None)), None);
+        // Answer cut off partway through
+        let reply = build_reply(&query, 0, Some("host.example.com"));
+        assert_eq!(parse_response(&reply[..reply.len() - 4]), None);
+    }
+
+    #[test]
+    fn resolves_through_stub_and_caches() {
+        let stub = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver = stub.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            // The first lookup fails, the second succeeds
+            for rcode in [2u8, 0u8] {
+                let (length, from) = stub.recv_from(&mut buffer).unwrap();
+                let hostname = if rcode == 0 {
+                    Some("host.example.com")
+                } else {
+                    None
+                };
+                stub.send_to(&build_reply(&buffer[..length], rcode, hostname), from)
+                    .unwrap();
+            }
+        });
+        let cache = std::env::temp_dir().join(format!("simplestats-dns-{}", std::process::id()));
+        let config = &YamlLoader::load_from_str(&format!(
+            "dns-resolver: \"{}\"\ndns-timeout: 2000\ndns-cache-file: \"{}\"\ndns-cache-ttl: 86400",
+            resolver,
+            cache.display()
+        ))
+        .unwrap()[0];
+        let ips = vec!["192.0.2.1"];
+
+        // SERVFAIL is neither returned nor cached
+        assert!(resolve_hostnames(&ips, config).is_empty());
+        assert!(read_cache(cache.to_str().unwrap()).is_empty());
+
+        let hostnames = resolve_hostnames(&ips, config);
+        assert_eq!(hostnames["192.0.2.1"], "host.example.com");
+        server.join().unwrap();
+
+        // The stub has stopped answering, so this comes from the cache
+        assert_eq!(
+            read_cache(cache.to_str().unwrap())["192.0.2.1"].0,
+            "host.example.com"
+        );
+        assert_eq!(
+            resolve_hostnames(&ips, config)["192.0.2.1"],
+            "host.example.com"
+        );
+        fs::remove_file(&cache).unwrap_or(());
+    }
+}
@@ -0,0 +1,72 @@
+use regex::Regex;
+use yaml_rust::Yaml;
+
+pub struct RouteRules {
+    rules: Vec<(Regex, String)>,
+    numeric_id: bool,
+    uuid: bool,
+    hash: bool,
+}
+
+pub fn get_route_rules(config: &Yaml) -> RouteRules {
+    let mut rules: Vec<(Regex, String)> = Vec::new();
+    for rule in config["route-rules"].as_vec().unwrap_or(&Vec::new()) {
+        let pattern = rule["pattern"].as_str().unwrap_or("");
+        let route = rule["route"].as_str().unwrap_or("");
+        match Regex::new(pattern) {
+            Ok(regex) => rules.push((regex, route.to_string())),
+            Err(e) => eprintln!("error: Invalid route pattern {}: {}", pattern, e),
+        }
+    }
+    let normalizers: Vec<&str> = match config["route-normalizers"].as_vec() {
+        Some(list) => list.iter().filter_map(|n| n.as_str()).collect(),
+        None => vec!["numeric-id", "uuid", "hash"],
+    };
+    return RouteRules {
+        rules,
+        numeric_id: normalizers.contains(&"numeric-id"),
+        uuid: normalizers.contains(&"uuid"),
+        hash: normalizers.contains(&"hash"),
+    };
+}
+
+// Maps a path onto its route. The first matching rule wins and its route may
+// refer to capture groups (e.g. "/docs/$1"). Paths that match no rule have
+// IDs, UUIDs and hashes in their segments replaced by placeholders.
+pub fn normalize_route(rules: &RouteRules, path: &str) -> String {
+    for (regex, route) in &rules.rules {
+        if let Some(captures) = regex.captures(path) {
+            let mut expanded = String::new();
+            captures.expand(route, &mut expanded);
+            return expanded;
+        }
+    }
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            if rules.numeric_id
+                && !segment.is_empty()
+                && segment.chars().all(|c| c.is_ascii_digit())
+            {
+                return ":id";
+            }
+            if rules.uuid && is_uuid(segment) {
+                return ":uuid";
+            }
+            if rules.hash && segment.len() >= 16 && segment.chars().all(|c| c.is_ascii_hexdigit()) {
+                return ":hash";
+            }
+            return segment;
+        })
+        .collect();
+    return segments.join("/");
+}
+
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    return groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(g, l)| g.len() == l && g.chars().all(|c| c.is_ascii_hexdigit()));
+}
@@ -0,0 +1,340 @@
+use crate::classify::get_request_path;
+use crate::Entry;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::FixedOffset;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use yaml_rust::Yaml;
+
+// (name, category, field, pattern)
+const BUILTIN_RULES: [(&str, &str, &str, &str); 7] = [
+    (
+        "path-traversal",
+        "Path traversal",
+        "request",
+        r"(?i)(\.\./|\.\.\\|/etc/(passwd|shadow|hosts)|/proc/self/|win\.ini|boot\.ini)",
+    ),
+    (
+        "sql-injection",
+        "SQL injection",
+        "request",
+        r"(?i)(\bunion\b.*\bselect\b|'\s*or\s+'?\w+'?\s*=\s*'?\w|\bor\s+1\s*=\s*1\b|\b(sleep|benchmark|pg_sleep)\s*\(|information_schema|\bwaitfor\s+delay\b|;\s*drop\s+table)",
+    ),
+    (
+        "xss",
+        "Cross-site scripting",
+        "request",
+        r"(?i)(<script|javascript:|<[a-z]+[^>]*\bon[a-z]+\s*=|document\.cookie|alert\s*\()",
+    ),
+    (
+        "command-injection",
+        "Command injection",
+        "request",
+        r"(?i)([;|`]\s*(cat|ls|id|uname|wget|curl|nc|bash|sh)\b|\$\(|/bin/(ba)?sh|cmd\.exe|powershell)",
+    ),
+    (
+        "sensitive-files",
+        "Sensitive file probe",
+        "request",
+        r"(?i)/(\.env|\.git/|\.svn/|\.hg/|\.htaccess|\.htpasswd|\.aws/|\.ssh/|\.ds_store|\.bash_history|id_rsa|wp-config\.php|web\.config|[^/ ]+\.(bak|old|sql|swp))\b",
+    ),
+    (
+        "cms-probes",
+        "CMS probe",
+        "request",
+        r"(?i)/(wp-login\.php|xmlrpc\.php|wp-admin/|wp-includes/|wp-content/plugins/|phpmyadmin|pma/|myadmin/|administrator/|cgi-bin/|vendor/phpunit/)",
+    ),
+    (
+        "scanners",
+        "Scanner",
+        "agent",
+        r"(?i)(sqlmap|nikto|nmap|masscan|zgrab|nuclei|wpscan|dirbuster|gobuster|ffuf|feroxbuster|acunetix|nessus|openvas|w3af|zmeu|jorgee|censysinspect|l9explore)",
+    ),
+];
+
+// Requests from one IP address for one URL matching rules of the same category.
+pub struct Finding {
+    pub category: String,
+    pub ip: String,
+    pub url: String,
+    pub hits: i32,
+    pub first_seen: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+}
+
+// Failed requests (401 or 403) on protected paths from one IP address or for
+// one username. Targets are the usernames tried by an IP address, or the IP
+// addresses that tried a username.
+pub struct BruteForce {
+    pub source: String,
+    pub failures: usize,
+    pub peak: usize,
+    pub first_seen: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+    pub targets: BTreeSet<String>,
+}
+
+// Computed once per run and shared by the security tables, the request rate
+// tables and the blocklist, since matching every rule against every entry and
+// sorting every address's requests is expensive.
+pub struct SecurityReport {
+    pub findings: Vec<Finding>,
+    // Brute force sources by IP address
+    pub brute_force: Vec<BruteForce>,
+    // Addresses that appear in either of the above
+    pub abusive_ips: HashSet<String>,
+    pub rates: HashMap<String, IpRate>,
+}
+
+pub struct IpRate {
+    pub per_second: usize,
+    pub per_minute: usize,
+    pub active_minutes: usize,
+    pub last_seen: DateTime<FixedOffset>,
+}
+
+struct SecurityRule {
+    category: String,
+    field: String,
+    regex: Regex,
+}
+
+// User-defined rules come first so that they can claim requests before the
+// bundled rules do.
+fn get_security_rules(config: &Yaml) -> Vec<SecurityRule> {
+    let mut rules: Vec<SecurityRule> = Vec::new();
+    for rule in config["security-rules"].as_vec().unwrap_or(&Vec::new()) {
+        let pattern = rule["pattern"].as_str().unwrap_or("");
+        let field = rule["field"].as_str().unwrap_or("request");
+        if !["request", "query", "agent"].contains(&field) {
+            eprintln!("error: Invalid security rule field: {}", field);
+            continue;
+        }
+        match Regex::new(pattern) {
+            Ok(regex) => rules.push(SecurityRule {
+                category: rule["category"].as_str().unwrap_or("Custom").to_string(),
+                field: field.to_string(),
+                regex,
+            }),
+            Err(e) => eprintln!("error: Invalid security rule pattern {}: {}", pattern, e),
+        }
+    }
+    let builtin: Vec<&str> = match config["security-builtin-rules"].as_vec() {
+        Some(list) => list.iter().filter_map(|r| r.as_str()).collect(),
+        None => BUILTIN_RULES.iter().map(|r| r.0).collect(),
+    };
+    for (name, category, field, pattern) in BUILTIN_RULES {
+        if builtin.contains(&name) {
+            rules.push(SecurityRule {
+                category: category.to_string(),
+                field: field.to_string(),
+                regex: Regex::new(pattern).unwrap(),
+            });
+        }
+    }
+    return rules;
+}
+
+pub fn get_security_report(entries: &Vec<Entry>, config: &Yaml) -> SecurityReport {
+    let findings = get_security_findings(entries, config);
+    let brute_force = get_brute_force(entries, config, false);
+    let mut abusive_ips: HashSet<String> = HashSet::new();
+    for finding in &findings {
+        abusive_ips.insert(finding.ip.clone());
+    }
+    for source in &brute_force {
+        abusive_ips.insert(source.source.clone());
+    }
+    return SecurityReport {
+        findings,
+        brute_force,
+        abusive_ips,
+        rates: get_ip_rates(entries),
+    };
+}
+
+fn get_security_findings(entries: &Vec<Entry>, config: &Yaml) -> Vec<Finding> {
+    let rules = get_security_rules(config);
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut indices: HashMap<(&str, &str, &str), usize> = HashMap::new();
+    for entry in entries {
+        let category = get_security_category(&rules, entry);
+        if category.is_none() {
+            continue;
+        }
+        let key = (
+            category.unwrap(),
+            entry.ip.as_str(),
+            get_request_path(entry),
+        );
+        match indices.get(&key) {
+            Some(i) => {
+                let finding = &mut findings[*i];
+                finding.hits += 1;
+                finding.first_seen = finding.first_seen.min(entry.time);
+                finding.last_seen = finding.last_seen.max(entry.time);
+            }
+            None => {
+                indices.insert(key, findings.len());
+                findings.push(Finding {
+                    category: key.0.to_string(),
+                    ip: key.1.to_string(),
+                    url: key.2.to_string(),
+                    hits: 1,
+                    first_seen: entry.time,
+                    last_seen: entry.time,
+                });
+            }
+        }
+    }
+    return findings;
+}
+
+// The category of the first rule matching the request, query string or user
+// agent. Requests and queries are percent-decoded first so that encoded
+// payloads (e.g. %2e%2e%2f) are caught as well.
+fn get_security_category<'a>(rules: &'a Vec<SecurityRule>, entry: &Entry) -> Option<&'a str> {
+    let request = decode(&entry.request);
+    let query = decode(&entry.query);
+    for rule in rules {
+        let text = match rule.field.as_str() {
+            "query" => &query,
+            "agent" => &entry.agent,
+            _ => &request,
+        };
+        if !text.is_empty() && rule.regex.is_match(text) {
+            return Some(&rule.category);
+        }
+    }
+    return None;
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+        i += 1;
+    }
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+// Finds IP addresses (or usernames if by_user is true) with at least
+// brute-force-threshold failed requests within any brute-force-window. The
+// peak is the largest number of failures seen within a single window.
+pub fn get_brute_force(entries: &Vec<Entry>, config: &Yaml, by_user: bool) -> Vec<BruteForce> {
+    let window = Duration::minutes(config["brute-force-window"].as_i64().unwrap_or(10).max(1));
+    let threshold = config["brute-force-threshold"]
+        .as_i64()
+        .unwrap_or(10)
+        .max(1) as usize;
+    let mut paths: Vec<Regex> = Vec::new();
+    for pattern in config["brute-force-paths"].as_vec().unwrap_or(&Vec::new()) {
+        let pattern = pattern.as_str().unwrap_or("");
+        match Regex::new(pattern) {
+            Ok(regex) => paths.push(regex),
+            Err(e) => eprintln!("error: Invalid brute force path {}: {}", pattern, e),
+        }
+    }
+    let mut failures: HashMap<&str, Vec<&Entry>> = HashMap::new();
+    for entry in entries {
+        if !entry.response.eq("401") && !entry.response.eq("403") {
+            continue;
+        }
+        let path = get_request_path(entry);
+        if !paths.is_empty() && !paths.iter().any(|p| p.is_match(path)) {
+            continue;
+        }
+        let source = if by_user { &entry.user } else { &entry.ip };
+        if source.is_empty() || source.eq("-") {
+            continue;
+        }
+        failures.entry(source).or_default().push(entry);
+    }
+    let mut sources: Vec<BruteForce> = Vec::new();
+    for (source, mut attempts) in failures {
+        if attempts.len() < threshold {
+            continue;
+        }
+        attempts.sort_by_key(|e| e.time);
+        let peak = get_peak_count(&attempts.iter().map(|e| e.time).collect(), window, true);
+        if peak < threshold {
+            continue;
+        }
+        sources.push(BruteForce {
+            source: source.to_string(),
+            failures: attempts.len(),
+            peak,
+            first_seen: attempts[0].time,
+            last_seen: attempts[attempts.len() - 1].time,
+            targets: attempts
+                .iter()
+                .map(|e| if by_user { &e.ip } else { &e.user })
+                .filter(|t| !t.is_empty() && !t.eq(&"-"))
+                .map(|t| t.to_string())
+                .collect(),
+        });
+    }
+    sources.sort_by(|a, b| b.peak.cmp(&a.peak).then(b.failures.cmp(&a.failures)));
+    return sources;
+}
+
+// The largest number of times falling within any one window. If inclusive
+// is true, times exactly one window apart count as falling within the same
+// window. Request rates use exclusive windows so that e.g. a per-second rate
+// only counts requests within the same second. The times must be sorted.
+pub fn get_peak_count(
+    times: &Vec<DateTime<FixedOffset>>,
+    window: Duration,
+    inclusive: bool,
+) -> usize {
+    let mut peak = 0;
+    let mut start = 0;
+    for end in 0..times.len() {
+        // A window always holds at least the time at its end
+        while start < end
+            && (times[end] - times[start] > window
+                || (!inclusive && times[end] - times[start] == window))
+        {
+            start += 1;
+        }
+        peak = peak.max(end - start + 1);
+    }
+    return peak;
+}
+
+// Peak requests per second and per minute, the number of minutes with at
+// least one request and the time of the latest request for each IP address.
+fn get_ip_rates(entries: &Vec<Entry>) -> HashMap<String, IpRate> {
+    let mut times: HashMap<&str, Vec<DateTime<FixedOffset>>> = HashMap::new();
+    for entry in entries {
+        times.entry(&entry.ip).or_default().push(entry.time);
+    }
+    let mut rates: HashMap<String, IpRate> = HashMap::new();
+    for (ip, mut times) in times {
+        times.sort();
+        let minutes: HashSet<i64> = times.iter().map(|t| t.timestamp() / 60).collect();
+        rates.insert(
+            ip.to_string(),
+            IpRate {
+                per_second: get_peak_count(&times, Duration::seconds(1), false),
+                per_minute: get_peak_count(&times, Duration::minutes(1), false),
+                active_minutes: minutes.len(),
+                last_seen: times[times.len() - 1],
+            },
+        );
+    }
+    return rates;
+}
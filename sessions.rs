@@ -0,0 +1,68 @@
+use crate::Entry;
+use std::collections::HashMap;
+use yaml_rust::Yaml;
+
+pub struct Session {
+    pub ip: String,
+    // Indices into the entry list, in chronological order
+    pub entries: Vec<usize>,
+}
+
+// Groups requests into visits. A visit is a run of requests from the same
+// visitor (the IP address, or the IP address and user agent) with no gap
+// longer than `session-timeout` minutes between them.
+pub fn get_sessions(entries: &Vec<Entry>, config: &Yaml) -> Vec<Session> {
+    let timeout = config["session-timeout"].as_i64().unwrap_or(30) * 60;
+    let include_agent = config["session-key"]
+        .as_str()
+        .unwrap_or("ip")
+        .eq_ignore_ascii_case("ip-agent");
+    let mut visitors: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let key = (
+            entry.ip.as_str(),
+            if include_agent {
+                entry.agent.as_str()
+            } else {
+                ""
+            },
+        );
+        if !visitors.contains_key(&key) {
+            visitors.insert(key, Vec::new());
+        }
+        visitors.get_mut(&key).unwrap().push(i);
+    }
+    let mut sessions: Vec<Session> = Vec::new();
+    for ((ip, _), mut indices) in visitors {
+        indices.sort_by_key(|i| entries[*i].time.timestamp());
+        let mut current: Vec<usize> = Vec::new();
+        for i in indices {
+            if let Some(last) = current.last() {
+                if entries[i].time.timestamp() - entries[*last].time.timestamp() > timeout {
+                    sessions.push(Session {
+                        ip: ip.to_string(),
+                        entries: current,
+                    });
+                    current = Vec::new();
+                }
+            }
+            current.push(i);
+        }
+        sessions.push(Session {
+            ip: ip.to_string(),
+            entries: current,
+        });
+    }
+    sessions.sort_by_key(|s| entries[s.entries[0]].time.timestamp());
+    return sessions;
+}
+
+// Marks the first request of every visit so that visits can be counted
+// alongside hits in tables that iterate over entries.
+pub fn get_session_starts(sessions: &Vec<Session>, count: usize) -> Vec<bool> {
+    let mut starts = vec![false; count];
+    for session in sessions {
+        starts[session.entries[0]] = true;
+    }
+    return starts;
+}
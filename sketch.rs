@@ -0,0 +1,438 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::hash::Hasher;
+use yaml_rust::Yaml;
+
+// Values are estimated to within 1% of their true value
+const RELATIVE_ACCURACY: f64 = 0.01;
+// Beyond this many buckets, the lowest buckets are merged together
+const MAX_BUCKETS: usize = 2048;
+
+// A quantile sketch in the style of DDSketch. Values are counted in
+// logarithmically sized buckets, so memory use depends only on the range of
+// values seen rather than on how many there were.
+pub struct QuantileSketch {
+    gamma: f64,
+    buckets: BTreeMap<i32, u64>,
+    zero_count: u64,
+    count: u64,
+    max: f64,
+}
+
+impl QuantileSketch {
+    pub fn new() -> QuantileSketch {
+        return QuantileSketch {
+            gamma: (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY),
+            buckets: BTreeMap::new(),
+            zero_count: 0,
+            count: 0,
+            max: 0.0,
+        };
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        if value > self.max {
+            self.max = value;
+        }
+        if value <= 0.0 {
+            self.zero_count += 1;
+            return;
+        }
+        let index = (value.ln() / self.gamma.ln()).ceil() as i32;
+        *self.buckets.entry(index).or_insert(0) += 1;
+        if self.buckets.len() > MAX_BUCKETS {
+            // Merged into the next bucket that exists so that no new bucket
+            // is created
+            let (_, count) = self.buckets.pop_first().unwrap();
+            *self.buckets.first_entry().unwrap().get_mut() += count;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        return self.count;
+    }
+
+    pub fn max(&self) -> f64 {
+        return self.max;
+    }
+
+    // q is between 0 and 1, e.g. 0.95 for the 95th percentile.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = (q * (self.count - 1) as f64) as u64;
+        if rank < self.zero_count {
+            return 0.0;
+        }
+        let mut seen = self.zero_count;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if seen > rank {
+                let estimate = 2.0 * self.gamma.powi(*index) / (self.gamma + 1.0);
+                return estimate.min(self.max);
+            }
+        }
+        return self.max;
+    }
+}
+
+// A HyperLogLog sketch for estimating the number of distinct values. The
+// standard error is about 1.04 / sqrt(registers). Registers are kept in a map
+// until enough of them are in use that a full array is smaller.
+pub struct HyperLogLog {
+    precision: u32,
+    sparse: HashMap<u32, u8>,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new(error: f64) -> HyperLogLog {
+        let registers = (1.04 / error).powi(2);
+        return HyperLogLog {
+            precision: registers.log2().ceil().clamp(4.0, 18.0) as u32,
+            sparse: HashMap::new(),
+            registers: Vec::new(),
+        };
+    }
+
+    pub fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - self.precision)) as u32;
+        let rank = ((hash << self.precision).leading_zeros() + 1).min(65 - self.precision) as u8;
+        self.set_register(index, rank);
+    }
+
+    // Both sketches must have been created with the same error bound.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        if other.registers.is_empty() {
+            for (index, rank) in &other.sparse {
+                self.set_register(*index, *rank);
+            }
+        } else {
+            for (index, rank) in other.registers.iter().enumerate() {
+                self.set_register(index as u32, *rank);
+            }
+        }
+    }
+
+    pub fn estimate(&self) -> usize {
+        let m = (1usize << self.precision) as f64;
+        let mut sum = 0.0;
+        let mut zeros = 0usize;
+        if self.registers.is_empty() {
+            zeros = m as usize - self.sparse.len();
+            sum += zeros as f64;
+            for rank in self.sparse.values() {
+                sum += 2f64.powi(-(*rank as i32));
+            }
+        } else {
+            for rank in &self.registers {
+                if *rank == 0 {
+                    zeros += 1;
+                }
+                sum += 2f64.powi(-(*rank as i32));
+            }
+        }
+        let alpha = match self.precision {
+            4 => 0.673,
+            5 => 0.697,
+            6 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let estimate = alpha * m * m / sum;
+        // Linear counting is more accurate while many registers are empty
+        if estimate <= 2.5 * m && zeros > 0 {
+            return (m * (m / zeros as f64).ln()).round() as usize;
+        }
+        return estimate.round() as usize;
+    }
+
+    fn set_register(&mut self, index: u32, rank: u8) {
+        if !self.registers.is_empty() {
+            let register = &mut self.registers[index as usize];
+            *register = (*register).max(rank);
+            return;
+        }
+        let register = self.sparse.entry(index).or_insert(0);
+        *register = (*register).max(rank);
+        // Each map entry takes several bytes, so switch once the array is smaller
+        if self.sparse.len() > (1usize << self.precision) / 8 {
+            self.registers = vec![0u8; 1usize << self.precision];
+            for (index, rank) in self.sparse.drain() {
+                self.registers[index as usize] = rank;
+            }
+        }
+    }
+}
+
+// Finds the most frequent values using the Space-Saving algorithm. Only
+// `capacity` values are tracked at once; when a new value arrives and every
+// counter is in use, the least frequent value is replaced and the new value
+// inherits its count.
+pub struct SpaceSaving {
+    capacity: usize,
+    counts: HashMap<String, u64>,
+    order: BTreeSet<(u64, String)>,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> SpaceSaving {
+        return SpaceSaving {
+            capacity,
+            counts: HashMap::new(),
+            order: BTreeSet::new(),
+        };
+    }
+
+    pub fn insert(&mut self, value: &str) {
+        if let Some(count) = self.counts.get_mut(value) {
+            self.order.remove(&(*count, value.to_string()));
+            *count += 1;
+            self.order.insert((*count, value.to_string()));
+            return;
+        }
+        let mut count = 1;
+        if self.counts.len() >= self.capacity {
+            let (min, evicted) = self.order.pop_first().unwrap();
+            self.counts.remove(&evicted);
+            count = min + 1;
+        }
+        self.counts.insert(value.to_string(), count);
+        self.order.insert((count, value.to_string()));
+    }
+
+    // The `k` values with the highest counts, most frequent first.
+    pub fn top(&self, k: usize) -> Vec<String> {
+        return self
+            .order
+            .iter()
+            .rev()
+            .take(k)
+            .map(|(_, value)| value.clone())
+            .collect();
+    }
+}
+
+// Counts distinct values either exactly or, if approximate-distinct is set,
+// with a HyperLogLog sketch.
+pub enum DistinctCounter<'a> {
+    Exact(HashSet<Cow<'a, str>>),
+    Approximate(HyperLogLog),
+}
+
+impl<'a> DistinctCounter<'a> {
+    pub fn new(config: &Yaml) -> DistinctCounter<'a> {
+        if is_approximate_distinct(config) {
+            return DistinctCounter::Approximate(HyperLogLog::new(get_hll_error(config)));
+        }
+        return DistinctCounter::Exact(HashSet::new());
+    }
+
+    pub fn insert(&mut self, value: &'a str) {
+        match self {
+            DistinctCounter::Exact(set) => {
+                set.insert(Cow::Borrowed(value));
+            }
+            DistinctCounter::Approximate(sketch) => sketch.insert(value),
+        }
+    }
+
+    pub fn merge(&mut self, other: &DistinctCounter<'a>) {
+        match (self, other) {
+            (DistinctCounter::Exact(set), DistinctCounter::Exact(other)) => {
+                set.extend(other.iter().cloned())
+            }
+            (DistinctCounter::Approximate(sketch), DistinctCounter::Approximate(other)) => {
+                sketch.merge(other)
+            }
+            _ => panic!("Cannot merge exact and approximate counters"),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match self {
+            DistinctCounter::Exact(set) => return set.len(),
+            DistinctCounter::Approximate(sketch) => return sketch.estimate(),
+        }
+    }
+}
+
+pub fn get_hll_error(config: &Yaml) -> f64 {
+    return config["hll-error"].as_f64().unwrap_or(0.01);
+}
+
+pub fn is_approximate_distinct(config: &Yaml) -> bool {
+    return config["approximate-distinct"].as_bool().unwrap_or(false);
+}
+
+// Estimated counts are prefixed with a tilde.
+pub fn format_distinct(count: usize, config: &Yaml) -> String {
+    if is_approximate_distinct(config) {
+        return format!("~{}", count);
+    }
+    return count.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A deterministic shuffle so that streams aren't sorted by frequency
+    fn shuffle(values: &mut Vec<String>) {
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        for i in (1..values.len()).rev() {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            values.swap(i, (state >> 33) as usize % (i + 1));
+        }
+    }
+
+    fn assert_within(estimate: f64, actual: f64, error: f64) {
+        assert!(
+            (estimate - actual).abs() <= actual * error,
+            "estimate {} is not within {}% of {}",
+            estimate,
+            error * 100.0,
+            actual
+        );
+    }
+
+    #[test]
+    fn hll_estimates_within_error_bound() {
+        for n in [10, 1000, 20000, 200000] {
+            let mut sketch = HyperLogLog::new(0.01);
+            for i in 0..n {
+                sketch.insert(&format!("192.0.2.{}", i));
+                // Repeats mustn't change the estimate
+                sketch.insert(&format!("192.0.2.{}", i / 2));
+            }
+            // Three standard errors
+            assert_within(sketch.estimate() as f64, n as f64, 0.03);
+        }
+    }
+
+    #[test]
+    fn hll_switches_from_sparse_to_dense() {
+        let mut sketch = HyperLogLog::new(0.01);
+        let registers = 1usize << sketch.precision;
+        for i in 0..registers / 16 {
+            sketch.insert(&i.to_string());
+        }
+        assert!(sketch.registers.is_empty());
+        let sparse = sketch.estimate();
+        assert_within(sparse as f64, (registers / 16) as f64, 0.03);
+        for i in registers / 16..registers {
+            sketch.insert(&i.to_string());
+        }
+        assert!(sketch.sparse.is_empty());
+        assert_eq!(sketch.registers.len(), registers);
+        assert_within(sketch.estimate() as f64, registers as f64, 0.03);
+    }
+
+    #[test]
+    fn hll_merges_sparse_and_dense() {
+        let mut small = HyperLogLog::new(0.01);
+        let mut large = HyperLogLog::new(0.01);
+        let mut both = HyperLogLog::new(0.01);
+        for i in 0..500 {
+            small.insert(&i.to_string());
+            both.insert(&i.to_string());
+        }
+        // Overlaps with the first sketch
+        for i in 250..50000 {
+            large.insert(&i.to_string());
+            both.insert(&i.to_string());
+        }
+        assert!(small.registers.is_empty());
+        assert!(!large.registers.is_empty());
+
+        let mut merged = HyperLogLog::new(0.01);
+        merged.merge(&small);
+        assert!(merged.registers.is_empty());
+        assert_eq!(merged.estimate(), small.estimate());
+        // Merging a dense sketch into a sparse one switches it to dense
+        merged.merge(&large);
+        assert_eq!(merged.registers, both.registers);
+        assert_within(merged.estimate() as f64, 50000.0, 0.03);
+
+        large.merge(&small);
+        assert_eq!(large.registers, both.registers);
+    }
+
+    #[test]
+    fn space_saving_finds_top_keys() {
+        // Key i appears 10000 / (i + 1) times
+        let mut stream: Vec<String> = Vec::new();
+        for i in 0..2000 {
+            for _ in 0..10000 / (i + 1) {
+                stream.push(format!("key-{}", i));
+            }
+        }
+        shuffle(&mut stream);
+        let mut top = SpaceSaving::new(100);
+        for value in &stream {
+            top.insert(value);
+        }
+        let expected: HashSet<String> = (0..10).map(|i| format!("key-{}", i)).collect();
+        let found: HashSet<String> = top.top(10).into_iter().collect();
+        assert_eq!(found, expected);
+        assert_eq!(top.top(1), vec![String::from("key-0")]);
+        assert_eq!(top.counts.len(), 100);
+    }
+
+    #[test]
+    fn quantiles_within_relative_accuracy() {
+        let mut sketch = QuantileSketch::new();
+        let mut values: Vec<f64> = Vec::new();
+        for i in 0..100000 {
+            // Spread over several orders of magnitude, like response times
+            values.push(1.0001f64.powi(i) * 50.0);
+        }
+        values.push(0.0);
+        for value in &values {
+            sketch.add(*value);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(sketch.count(), values.len() as u64);
+        assert_eq!(sketch.quantile(0.0), 0.0);
+        assert!(sketch.quantile(1.0) <= sketch.max());
+        for q in [0.01, 0.25, 0.5, 0.75, 0.9, 0.95, 0.99, 0.999] {
+            let actual = values[(q * (values.len() - 1) as f64) as usize];
+            assert_within(sketch.quantile(q), actual, RELATIVE_ACCURACY);
+        }
+    }
+
+    #[test]
+    fn collapses_lowest_buckets_into_existing_ones() {
+        let mut sketch = QuantileSketch::new();
+        // Far below every other value, so the bucket after it is empty
+        sketch.add(1e-30);
+        let mut values: Vec<f64> = Vec::new();
+        for i in 1..=MAX_BUCKETS as i32 + 100 {
+            // The middle of bucket i
+            values.push(sketch.gamma.powf(i as f64 - 0.5));
+        }
+        for value in &values {
+            sketch.add(*value);
+        }
+        assert_eq!(sketch.buckets.len(), MAX_BUCKETS);
+        assert_eq!(sketch.buckets.values().sum::<u64>(), sketch.count());
+        // The tiny value and the lowest 100 values were merged upwards, so
+        // only the lowest quantiles are overestimated
+        assert_within(sketch.quantile(0.0), values[100], RELATIVE_ACCURACY);
+        for q in [0.1, 0.5, 0.9, 0.99] {
+            let actual = values[(q * values.len() as f64) as usize - 1];
+            assert_within(sketch.quantile(q), actual, RELATIVE_ACCURACY);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono_tz::Tz;
+use yaml_rust::Yaml;
+
+// Reads report-timezone. None means times keep the offset they were logged
+// with.
+pub fn get_report_timezone(config: &Yaml) -> Option<Tz> {
+    let name = config["report-timezone"].as_str().unwrap_or("log");
+    if name.eq("log") {
+        return None;
+    }
+    match name.parse::<Tz>() {
+        Ok(tz) => return Some(tz),
+        Err(_) => {
+            eprintln!(
+                "error: Unknown timezone {}, using the log's timezone instead",
+                name
+            );
+            return None;
+        }
+    }
+}
+
+// Converts a time into the report's timezone. Each time is converted
+// separately, so times on either side of a DST change get the right offset.
+pub fn to_report_time(date: &DateTime<FixedOffset>, timezone: Option<Tz>) -> DateTime<FixedOffset> {
+    match timezone {
+        Some(tz) => return date.with_timezone(&tz).fixed_offset(),
+        None => return *date,
+    }
+}
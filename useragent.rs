@@ -0,0 +1,61 @@
+use woothee::parser::Parser;
+use woothee::woothee::VALUE_UNKNOWN;
+
+pub struct UserAgent {
+    pub browser: String,
+    pub os: String,
+    pub device: String,
+}
+
+pub fn parse_user_agent(parser: &Parser, agent: &str) -> UserAgent {
+    let result = parser.parse(agent);
+    if result.is_none() {
+        return UserAgent {
+            browser: String::from("(unknown)"),
+            os: String::from("(unknown)"),
+            device: String::from("(unknown)"),
+        };
+    }
+    let result = result.unwrap();
+    return UserAgent {
+        browser: format_family_version(result.name, result.version, 1),
+        os: format_family_version(result.os, &result.os_version, 2),
+        device: get_device_class(result.category, agent).to_string(),
+    };
+}
+
+// Keeps only the leading `components` parts of a dotted version string,
+// so that e.g. every Chrome 103 build is grouped together.
+fn format_family_version(family: &str, version: &str, components: usize) -> String {
+    if family.eq(VALUE_UNKNOWN) {
+        return String::from("(unknown)");
+    }
+    let short: Vec<&str> = version.split(['.', '_']).take(components).collect();
+    let short = short.join(".");
+    // Some families already carry their version (e.g. "Windows 10")
+    if version.eq(VALUE_UNKNOWN)
+        || short.is_empty()
+        || family.ends_with(|c: char| c.is_ascii_digit())
+    {
+        return family.to_string();
+    }
+    return format!("{} {}", family, short);
+}
+
+fn get_device_class(category: &str, agent: &str) -> &'static str {
+    match category {
+        "crawler" => return "Bot",
+        "pc" => return "Desktop",
+        "smartphone" | "mobilephone" => {
+            if agent.contains("iPad")
+                || agent.contains("Tablet")
+                || (agent.contains("Android") && !agent.contains("Mobile"))
+            {
+                return "Tablet";
+            }
+            return "Mobile";
+        }
+        "appliance" => return "Appliance",
+        _ => return "Other",
+    }
+}